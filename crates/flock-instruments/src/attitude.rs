@@ -1,12 +1,15 @@
-use eframe::{
-    egui::{Rounding, Sense, Widget},
+use egui::{
     emath::Align2,
-    epaint::{FontId, HsvaGamma, Pos2, Shape, Stroke, Vec2},
+    epaint::{FontId, HsvaGamma, Pos2, Rect, Shape, Stroke, Vec2},
+    Painter, Rounding, Sense, Widget,
 };
 
+use crate::validity::Validity;
+
 pub struct AttitudeIndicator {
     pitch: f32,
     roll: f32,
+    validity: Validity,
 }
 
 impl AttitudeIndicator {
@@ -15,7 +18,16 @@ impl AttitudeIndicator {
         let pitch = f32::abs((pitch + 180.0).rem_euclid(360.0)) - 180.0;
         let roll = f32::abs((roll + 180.0).rem_euclid(360.0)) - 180.0;
 
-        Self { pitch, roll }
+        Self {
+            pitch,
+            roll,
+            validity: Validity::Valid,
+        }
+    }
+
+    pub fn validity(mut self, validity: Validity) -> Self {
+        self.validity = validity;
+        self
     }
 }
 
@@ -48,7 +60,7 @@ const SKY: HsvaGamma = HsvaGamma {
 };
 
 impl Widget for AttitudeIndicator {
-    fn ui(self, ui: &mut eframe::egui::Ui) -> eframe::egui::Response {
+    fn ui(self, ui: &mut egui::Ui) -> egui::Response {
         let space = ui.available_size();
         let (response, painter) = ui.allocate_painter(
             Vec2::splat(space.min_elem()),
@@ -240,6 +252,8 @@ impl Widget for AttitudeIndicator {
             });
         }
 
+        self.validity.paint(&painter, bounds);
+
         response
     }
 }
@@ -247,6 +261,7 @@ impl Widget for AttitudeIndicator {
 pub struct AttitudeIndicatorRectangular {
     pitch: f32,
     roll: f32,
+    validity: Validity,
 }
 
 impl AttitudeIndicatorRectangular {
@@ -255,228 +270,228 @@ impl AttitudeIndicatorRectangular {
         let pitch = f32::abs((pitch + 180.0).rem_euclid(360.0)) - 180.0;
         let roll = f32::abs((roll + 180.0).rem_euclid(360.0)) - 180.0;
 
-        Self { pitch, roll }
+        // Past the vertical, the same attitude is reached by pitching less and flying inverted
+        let (pitch, roll) = if pitch > 90.0 {
+            (180.0 - pitch, roll + 180.0)
+        } else if pitch < -90.0 {
+            (-180.0 - pitch, roll + 180.0)
+        } else {
+            (pitch, roll)
+        };
+        let roll = (roll + 180.0).rem_euclid(360.0) - 180.0;
+
+        Self {
+            pitch,
+            roll,
+            validity: Validity::Valid,
+        }
     }
-}
 
-impl Widget for AttitudeIndicatorRectangular {
-    fn ui(self, ui: &mut eframe::egui::Ui) -> eframe::egui::Response {
-        let space = ui.available_size();
-        let (response, painter) = ui.allocate_painter(
-            Vec2::splat(space.min_elem()),
-            Sense::focusable_noninteractive(),
-        );
-        let bounds = response.rect;
+    pub fn validity(mut self, validity: Validity) -> Self {
+        self.validity = validity;
+        self
+    }
+
+    /// Paint the horizon into an arbitrary rectangle, for composing into larger displays
+    pub fn paint(&self, painter: &Painter, bounds: Rect) {
+        let painter = painter.with_clip_rect(bounds.intersect(painter.clip_rect()));
 
         let size = f32::min(bounds.width(), bounds.height());
+        let stroke_width = size * 0.006;
+        // Degrees of pitch between the center and the top and bottom edge of the square
+        let pixels_per_degree = size / 2.0 / 30.0;
+
+        // Along the horizon, towards the right wing, and towards the sky
+        let along = Vec2::angled(f32::to_radians(-self.roll));
+        let up = along.rot90();
+        let horizon = bounds.center() - up * self.pitch * pixels_per_degree;
+
+        // Sky and ground
+        painter.add(Shape::rect_filled(bounds, Rounding::same(1.0), SKY));
+        let ground = clip_half_plane(
+            &[
+                bounds.left_top(),
+                bounds.right_top(),
+                bounds.right_bottom(),
+                bounds.left_bottom(),
+            ],
+            horizon,
+            -up,
+        );
+        if ground.len() >= 3 {
+            painter.add(Shape::convex_polygon(ground, GROUND, Stroke::NONE));
+        }
+        painter.line_segment(
+            [
+                horizon - along * bounds.size().length(),
+                horizon + along * bounds.size().length(),
+            ],
+            Stroke::new(stroke_width, WHITE),
+        );
 
-        let (pitch, circle_color, other_color) = if self.pitch > -90.0 && self.pitch <= 90.0 {
-            (self.pitch, SKY, GROUND)
-        } else {
-            (self.pitch + 180.0, GROUND, SKY)
-        };
+        // Pitch ladder, clipped to the center so it does not collide with the roll scale
+        {
+            let ladder = painter.with_clip_rect(
+                Rect::from_center_size(bounds.center(), Vec2::new(size * 0.6, size * 0.6))
+                    .intersect(painter.clip_rect()),
+            );
+            let font = FontId::monospace(size * 0.035);
+
+            for angle in (-90..=90).step_by(5).filter(|angle| *angle != 0) {
+                let angle = angle as f32;
+
+                if f32::abs(angle - self.pitch) > 25.0 {
+                    continue;
+                }
+
+                let center = horizon + up * angle * pixels_per_degree;
+                let half_width = size * if angle % 10.0 == 0.0 { 0.12 } else { 0.05 };
+
+                ladder.line_segment(
+                    [center - along * half_width, center + along * half_width],
+                    Stroke::new(stroke_width, WHITE),
+                );
+
+                if angle % 10.0 == 0.0 {
+                    for side in [-1.0, 1.0] {
+                        ladder.text(
+                            center + along * side * (half_width + size * 0.04),
+                            Align2::CENTER_CENTER,
+                            format!("{:.0}", f32::abs(angle)),
+                            font.clone(),
+                            WHITE.into(),
+                        );
+                    }
+                }
+            }
+        }
 
-        let left_angle = (pitch + self.roll).rem_euclid(360.0);
-        let right_angle = (self.roll - pitch + 180.0).rem_euclid(360.0);
+        // Roll scale, fixed to the display
+        let roll_center = bounds.center();
+        let roll_radius = size * 0.42;
+        let scale_point = |angle: f32, radius: f32| {
+            roll_center + Vec2::angled(f32::to_radians(angle - 90.0)) * radius
+        };
 
-        let left_point = match left_angle {
-            0.0..=45.0 | 315.0..=365.0 => {
-                bounds.left_center()
-                    + Vec2::new(0.0, f32::to_radians(left_angle).tan() * size / 2.0)
-            }
-            45.0..=135.0 => {
-                bounds.center_bottom()
-                    - Vec2::new(size / (f32::to_radians(left_angle).tan() * 2.0), 0.0)
-            }
-            135.0..=225.0 => {
-                bounds.right_center()
-                    - Vec2::new(0.0, f32::to_radians(left_angle).tan() * size / 2.0)
-            }
-            225.0..=315.0 => {
-                bounds.center_top()
-                    + Vec2::new(size / (f32::to_radians(left_angle).tan() * 2.0), 0.0)
+        painter.add(Shape::line(
+            (-60..=60)
+                .step_by(5)
+                .map(|angle| scale_point(angle as f32, roll_radius))
+                .collect(),
+            Stroke::new(stroke_width, WHITE),
+        ));
+
+        let markings: &[(&[f32], f32)] = &[
+            (&[-20.0, -10.0, 10.0, 20.0], 0.03),
+            (&[-60.0, -30.0, 0.0, 30.0, 60.0], 0.06),
+        ];
+        for (angles, length) in markings {
+            for angle in *angles {
+                painter.line_segment(
+                    [
+                        scale_point(*angle, roll_radius),
+                        scale_point(*angle, roll_radius + size * length),
+                    ],
+                    Stroke::new(stroke_width, WHITE),
+                );
             }
-            _ => unreachable!(),
-        };
-        let right_point = bounds.center();
+        }
+        for angle in [-45.0, 45.0] {
+            painter.circle_filled(
+                scale_point(angle, roll_radius + size * 0.02),
+                size * 0.008,
+                WHITE,
+            );
+        }
 
-        // let right_point =
-        //     bounds.right_center() + Vec2::new(0.0, f32::to_radians(right_angle).sin() * size / 2.0);
-        let center_point = Pos2::new(
-            (left_point.x + right_point.x) / 2.0,
-            (left_point.y + right_point.y) / 2.0,
+        // Roll pointer, rotating with the horizon
+        painter.add(Shape::convex_polygon(
+            vec![
+                scale_point(-self.roll, roll_radius - size * 0.005),
+                scale_point(-self.roll + 3.0, roll_radius - size * 0.05),
+                scale_point(-self.roll - 3.0, roll_radius - size * 0.05),
+            ],
+            RED,
+            Stroke::NONE,
+        ));
+
+        // Fixed aircraft symbol
+        let wing = Stroke::new(size * 0.012, RED);
+        for side in [-1.0, 1.0] {
+            painter.add(Shape::line(
+                vec![
+                    bounds.center() + Vec2::new(side * size * 0.3, 0.0),
+                    bounds.center() + Vec2::new(side * size * 0.12, 0.0),
+                    bounds.center() + Vec2::new(side * size * 0.12, size * 0.04),
+                ],
+                wing,
+            ));
+        }
+        painter.circle_filled(bounds.center(), size * 0.012, RED);
+
+        // Readouts
+        let font = FontId::monospace(size * 0.05);
+        painter.text(
+            bounds.left_bottom() + Vec2::new(size * 0.03, -size * 0.03),
+            Align2::LEFT_BOTTOM,
+            format!(
+                "Roll \n{:03.0}°{}",
+                f32::abs(self.roll),
+                if self.roll < 0.0 {
+                    'L'
+                } else if self.roll > 0.0 {
+                    'R'
+                } else {
+                    ' '
+                }
+            ),
+            font.clone(),
+            WHITE.into(),
         );
+        painter.text(
+            bounds.right_bottom() - Vec2::splat(size * 0.03),
+            Align2::RIGHT_BOTTOM,
+            format!("Pitch\n{:+04.0}°", self.pitch),
+            font,
+            WHITE.into(),
+        );
+    }
+}
 
-        let tangent = (left_point - right_point).normalized();
-        let normal = tangent.rot90();
-
-        painter.extend([
-            Shape::rect_filled(bounds, Rounding::same(1.0), circle_color),
-            Shape::line_segment([left_point, right_point], Stroke::new(5.0, other_color)),
-        ]);
+impl Widget for AttitudeIndicatorRectangular {
+    fn ui(self, ui: &mut egui::Ui) -> egui::Response {
+        let space = ui.available_size();
+        let (response, painter) = ui.allocate_painter(
+            Vec2::splat(space.min_elem()),
+            Sense::focusable_noninteractive(),
+        );
 
-        // painter.extend([
-        //     // Background
-        //     Shape::circle_filled(bounds.center(), radius, circle_color),
-        //     // Horizon
-        //     Shape::convex_polygon(
-        //         [left_point, right_point]
-        //             .into_iter()
-        //             .chain(
-        //                 std::iter::successors(Some(right_angle), |angle| {
-        //                     let angle = (angle - 5.0).rem_euclid(360.0);
-
-        //                     if f32::abs(angle - left_angle) <= 5.0 {
-        //                         None
-        //                     } else {
-        //                         Some(angle)
-        //                     }
-        //                 })
-        //                 .skip(1)
-        //                 .map(|angle| {
-        //                     bounds.center() + Vec2::angled(f32::to_radians(angle)) * radius
-        //                 }),
-        //             )
-        //             .collect::<Vec<_>>(),
-        //         other_color,
-        //         Stroke::NONE,
-        //     ),
-        //     // Horizon degrees markers
-        //     Shape::Vec(
-        //         [-20.0, -15.0, -10.0, -5.0, 0.0, 5.0, 10.0, 15.0, 20.0]
-        //             .into_iter()
-        //             .map(|angle| {
-        //                 Shape::line_segment(
-        //                     {
-        //                         let upwards = normal * radius * f32::sin(f32::to_radians(angle));
-
-        //                         let half_width = if angle % 10.0 == 0.0 { 0.2 } else { 0.1 };
-
-        //                         let inwards = tangent * radius * half_width;
-
-        //                         [
-        //                             center_point + upwards - inwards,
-        //                             center_point + upwards + inwards,
-        //                         ]
-        //                     },
-        //                     Stroke::new(radius * 0.01, white),
-        //                 )
-        //             })
-        //             .collect(),
-        //     ),
-        //     // Red level markers
-        //     Shape::line_segment(
-        //         [
-        //             bounds.center() + Vec2::X * radius * 0.3,
-        //             bounds.center() + Vec2::X * radius * 0.7,
-        //         ],
-        //         Stroke::new(radius * 0.01, red),
-        //     ),
-        //     Shape::line_segment(
-        //         [
-        //             bounds.center() - Vec2::X * radius * 0.3,
-        //             bounds.center() - Vec2::X * radius * 0.7,
-        //         ],
-        //         Stroke::new(radius * 0.01, red),
-        //     ),
-        //     // Red center marker
-        //     Shape::line(
-        //         vec![
-        //             bounds.center() + Vec2::angled(f32::to_radians(15.0)) * radius * 0.2,
-        //             bounds.center(),
-        //             bounds.center() + Vec2::angled(f32::to_radians(180.0 - 15.0)) * radius * 0.2,
-        //         ],
-        //         Stroke::new(radius * 0.01, red),
-        //     ),
-        //     // Pitch
-        //     painter.fonts(|fonts| {
-        //         Shape::Vec(vec![
-        //             Shape::text(
-        //                 fonts,
-        //                 bounds.center() + Vec2::X * radius * 0.5,
-        //                 Align2::CENTER_CENTER,
-        //                 format!("Pitch\n{:+04.0}°", self.pitch),
-        //                 FontId::monospace(0.1 * radius),
-        //                 WHITE.into(),
-        //             ),
-        //             Shape::text(
-        //                 fonts,
-        //                 bounds.center() - Vec2::X * radius * 0.5,
-        //                 Align2::CENTER_CENTER,
-        //                 format!(
-        //                     "Roll \n{:03.0}°{}",
-        //                     f32::abs(self.roll),
-        //                     if self.roll < 0.0 {
-        //                         'L'
-        //                     } else if self.roll > 0.0 {
-        //                         'R'
-        //                     } else {
-        //                         ' '
-        //                     }
-        //                 ),
-        //                 FontId::monospace(0.1 * radius),
-        //                 WHITE.into(),
-        //             ),
-        //         ])
-        //     }),
-        // ]);
-
-        // {
-        //     let markings: &[(&[f32], std::ops::RangeInclusive<f32>, f32)] = &[
-        //         (&[-20.0, -10.0, 10.0, 20.0], 0.8..=0.9, 0.01),
-        //         (
-        //             &[-90.0, -60.0, -30.0, 0.0, 30.0, 60.0, 90.0],
-        //             0.8..=1.0,
-        //             0.02,
-        //         ),
-        //     ];
-
-        //     let markings = markings.iter().flat_map(|(angle, length, width)| {
-        //         angle.iter().map(move |angle| {
-        //             Shape::line_segment(
-        //                 [
-        //                     bounds.center()
-        //                         + Vec2::angled(f32::to_radians(angle - 90.0))
-        //                             * radius
-        //                             * *length.end(),
-        //                     bounds.center()
-        //                         + Vec2::angled(f32::to_radians(angle - 90.0))
-        //                             * radius
-        //                             * *length.start(),
-        //                 ],
-        //                 Stroke::new(radius * width, WHITE),
-        //             )
-        //         })
-        //     });
-
-        //     let dots = [-45.0, 45.0].iter().map(|angle| {
-        //         Shape::circle_filled(
-        //             bounds.center() + Vec2::angled(f32::to_radians(angle - 90.0)) * radius * 0.9,
-        //             radius * 0.02,
-        //             WHITE,
-        //         )
-        //     });
-
-        //     painter.extend(markings.chain(dots));
-
-        //     // Roll Indicator
-        //     painter.add({
-        //         let top = Vec2::angled(f32::to_radians(self.roll - 90.0));
-        //         let bottom_left = Vec2::angled(f32::to_radians(self.roll - 95.0));
-        //         let bottom_right = Vec2::angled(f32::to_radians(self.roll - 85.0));
-
-        //         Shape::convex_polygon(
-        //             vec![
-        //                 bounds.center() + top * radius * 0.85,
-        //                 bounds.center() + bottom_right * radius * 0.7,
-        //                 bounds.center() + bottom_left * radius * 0.7,
-        //             ],
-        //             RED,
-        //             Stroke::NONE,
-        //         )
-        //     });
-        // }
+        self.paint(&painter, response.rect);
+        self.validity.paint(&painter, response.rect);
 
         response
     }
 }
+
+/// Clip a convex polygon to the half plane on the side of `point` that `normal` points to
+fn clip_half_plane(polygon: &[Pos2], point: Pos2, normal: Vec2) -> Vec<Pos2> {
+    let distance = |vertex: Pos2| (vertex - point).dot(normal);
+
+    polygon
+        .iter()
+        .zip(polygon.iter().cycle().skip(1))
+        .flat_map(|(&from, &to)| {
+            let (from_distance, to_distance) = (distance(from), distance(to));
+
+            let crossing = (from_distance.signum() != to_distance.signum()
+                && from_distance != 0.0
+                && to_distance != 0.0)
+                .then(|| from.lerp(to, from_distance / (from_distance - to_distance)));
+
+            (from_distance >= 0.0)
+                .then_some(from)
+                .into_iter()
+                .chain(crossing)
+        })
+        .collect()
+}
@@ -1,23 +1,34 @@
-use eframe::{
-    egui::{Sense, Widget},
+use egui::{
     emath::Align2,
-    epaint::{FontId, Hsva, HsvaGamma, Rect, RectShape, Rounding, Shape, Stroke, Vec2},
+    epaint::{FontId, Hsva, HsvaGamma, Pos2, Rect, RectShape, Rounding, Shape, Stroke, Vec2},
+    Sense, Widget,
 };
 
+use crate::validity::Validity;
+
 pub struct HeadingIndicator {
     heading: f32,
+    validity: Validity,
 }
 
 impl HeadingIndicator {
     pub fn new(heading: f32) -> Self {
         let heading = heading.rem_euclid(360.0).abs();
 
-        HeadingIndicator { heading }
+        HeadingIndicator {
+            heading,
+            validity: Validity::Valid,
+        }
+    }
+
+    pub fn validity(mut self, validity: Validity) -> Self {
+        self.validity = validity;
+        self
     }
 }
 
 impl Widget for HeadingIndicator {
-    fn ui(self, ui: &mut eframe::egui::Ui) -> eframe::egui::Response {
+    fn ui(self, ui: &mut egui::Ui) -> egui::Response {
         let space = ui.available_size();
         let (response, painter) =
             ui.allocate_painter(Vec2::splat(space.min_elem()), Sense::hover());
@@ -32,10 +43,6 @@ impl Widget for HeadingIndicator {
         let background_color = Hsva::new(0.0, 0.0, 0.02, 1.0);
         let border_color = Hsva::new(0.0, 0.0, 1.0, 1.0);
 
-        let front = Vec2::angled(f32::to_radians(self.heading - 90.0));
-        let rear_right = Vec2::angled(f32::to_radians(self.heading + 150.0 - 90.0));
-        let rear_left = Vec2::angled(f32::to_radians(self.heading + 210.0 - 90.0));
-
         painter.extend([
             // Circle background
             Shape::circle_filled(bounds.center(), radius, Hsva::new(0.0, 0.0, 0.02, 1.0)),
@@ -148,37 +155,14 @@ impl Widget for HeadingIndicator {
             ]
         }));
 
+        // Arrow heading indicator
+        painter.extend(heading_arrow(
+            bounds.center(),
+            self.heading,
+            radius,
+            size / 100.0,
+        ));
         painter.extend([
-            // Arrow heading indicator
-            Shape::convex_polygon(
-                vec![
-                    bounds.center() + front * radius / 2.0,
-                    bounds.center() + rear_left * radius / 2.0, // Vec2::new(-size / 6.0, size / 4.0),
-                    bounds.center() + front * -radius / 4.0,
-                ],
-                Hsva::new(0.0, 1.0, 0.8, 1.0),
-                Stroke::NONE,
-            ),
-            Shape::convex_polygon(
-                vec![
-                    bounds.center() + front * radius / 2.0,
-                    bounds.center() + rear_right * radius / 2.0,
-                    bounds.center() + front * -radius / 4.0,
-                ],
-                Hsva::new(0.0, 1.0, 0.8, 1.0),
-                Stroke::NONE,
-            ),
-            Shape::line(
-                vec![
-                    bounds.center() + front * -radius / 4.0,
-                    bounds.center() + rear_left * radius / 2.0,
-                    bounds.center() + front * radius / 2.0,
-                    bounds.center() + rear_right * radius / 2.0,
-                    bounds.center() + front * -radius / 4.0,
-                    bounds.center() + front * radius,
-                ],
-                (size / 100.0, Hsva::new(0.0, 0.9, 0.5, 1.0)),
-            ),
             // Heading text box
             Shape::Rect(RectShape::new(
                 Rect::from_center_size(bounds.center(), Vec2::new(size * 0.40, size * 0.25)),
@@ -200,7 +184,7 @@ impl Widget for HeadingIndicator {
                     fonts,
                     bounds.center(),
                     Align2::CENTER_CENTER,
-                    format!("{:03.0}°", self.heading),
+                    format!("{:03.0}°", self.heading.round().rem_euclid(360.0)),
                     FontId::monospace(bounds.height() * 0.15),
                     Hsva {
                         a: 0.75,
@@ -212,6 +196,47 @@ impl Widget for HeadingIndicator {
             Shape::circle_stroke(bounds.center(), radius, (circle_stroke, border_color)),
         ]);
 
+        self.validity.paint(&painter, bounds);
+
         response
     }
 }
+
+/// The arrow pointing in the direction of `heading`, `radius` being the length of its pointer line
+pub fn heading_arrow(center: Pos2, heading: f32, radius: f32, stroke_width: f32) -> [Shape; 3] {
+    let front = Vec2::angled(f32::to_radians(heading - 90.0));
+    let rear_right = Vec2::angled(f32::to_radians(heading + 150.0 - 90.0));
+    let rear_left = Vec2::angled(f32::to_radians(heading + 210.0 - 90.0));
+
+    [
+        Shape::convex_polygon(
+            vec![
+                center + front * radius / 2.0,
+                center + rear_left * radius / 2.0, // Vec2::new(-size / 6.0, size / 4.0),
+                center + front * -radius / 4.0,
+            ],
+            Hsva::new(0.0, 1.0, 0.8, 1.0),
+            Stroke::NONE,
+        ),
+        Shape::convex_polygon(
+            vec![
+                center + front * radius / 2.0,
+                center + rear_right * radius / 2.0,
+                center + front * -radius / 4.0,
+            ],
+            Hsva::new(0.0, 1.0, 0.8, 1.0),
+            Stroke::NONE,
+        ),
+        Shape::line(
+            vec![
+                center + front * -radius / 4.0,
+                center + rear_left * radius / 2.0,
+                center + front * radius / 2.0,
+                center + rear_right * radius / 2.0,
+                center + front * -radius / 4.0,
+                center + front * radius,
+            ],
+            (stroke_width, Hsva::new(0.0, 0.9, 0.5, 1.0)),
+        ),
+    ]
+}
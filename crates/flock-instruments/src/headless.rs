@@ -0,0 +1,299 @@
+//! Rendering of instruments to images without a window or GPU
+//!
+//! egui is run without a backend, and the meshes it tessellates are rasterized on the CPU the
+//! same way the GPU backends draw them: premultiplied alpha, blended in gamma space. egui
+//! feathers the edges of its shapes itself, so no further anti-aliasing is needed.
+
+use std::collections::HashMap;
+
+use egui::{
+    epaint::{
+        textures::{TextureFilter, TexturesDelta},
+        ClippedPrimitive, ImageData, Mesh, Primitive, Vertex,
+    },
+    CentralPanel, Color32, Context, Pos2, RawInput, Rect, TextureId, Ui, Vec2, ViewportId,
+};
+use image::RgbaImage;
+
+/// Renders egui contents to images, keeping the textures (like the font atlas) across frames
+pub struct HeadlessRenderer {
+    ctx: Context,
+    textures: HashMap<TextureId, Texture>,
+}
+
+impl Default for HeadlessRenderer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl HeadlessRenderer {
+    pub fn new() -> Self {
+        Self {
+            ctx: Context::default(),
+            textures: HashMap::new(),
+        }
+    }
+
+    /// The context the contents are drawn in, to change its style
+    pub fn context(&self) -> &Context {
+        &self.ctx
+    }
+
+    /// Draw `add_contents` in a central panel filling an image of `size` pixels
+    ///
+    /// `pixels_per_point` scales the contents, like the scale factor of a screen.
+    pub fn render(
+        &mut self,
+        size: [u32; 2],
+        pixels_per_point: f32,
+        mut add_contents: impl FnMut(&mut Ui),
+    ) -> RgbaImage {
+        let mut input = RawInput {
+            screen_rect: Some(Rect::from_min_size(
+                Pos2::ZERO,
+                Vec2::new(size[0] as f32, size[1] as f32) / pixels_per_point,
+            )),
+            ..Default::default()
+        };
+        input
+            .viewports
+            .entry(ViewportId::ROOT)
+            .or_default()
+            .native_pixels_per_point = Some(pixels_per_point);
+
+        // Layouts that size themselves after the previous frame settle in the second pass
+        let mut output = None;
+        for _ in 0..2 {
+            let full_output = self.ctx.run(input.clone(), |ctx| {
+                CentralPanel::default().show(ctx, |ui| add_contents(ui));
+            });
+            self.update_textures(&full_output.textures_delta);
+            output = Some(full_output);
+        }
+        let output = output.expect("rendered at least once");
+
+        let primitives = self.ctx.tessellate(output.shapes, output.pixels_per_point);
+        let mut canvas = Canvas::new(size);
+        for ClippedPrimitive {
+            clip_rect,
+            primitive,
+        } in &primitives
+        {
+            // Paint callbacks need a GPU, and no instrument uses them
+            let Primitive::Mesh(mesh) = primitive else {
+                continue;
+            };
+            let Some(texture) = self.textures.get(&mesh.texture_id) else {
+                continue;
+            };
+
+            canvas.paint(mesh, *clip_rect, output.pixels_per_point, texture);
+        }
+
+        canvas.into_image()
+    }
+
+    fn update_textures(&mut self, delta: &TexturesDelta) {
+        for (id, delta) in &delta.set {
+            let (size, pixels) = match &delta.image {
+                ImageData::Color(image) => (image.size, image.pixels.clone()),
+                ImageData::Font(image) => (image.size, image.srgba_pixels(None).collect()),
+            };
+
+            match delta.pos {
+                None => {
+                    self.textures.insert(
+                        *id,
+                        Texture {
+                            size,
+                            pixels,
+                            filter: delta.options.magnification,
+                        },
+                    );
+                }
+                Some([x, y]) => {
+                    let Some(texture) = self.textures.get_mut(id) else {
+                        continue;
+                    };
+
+                    for (row, patch) in pixels.chunks_exact(size[0]).enumerate() {
+                        let start = (y + row) * texture.size[0] + x;
+                        texture.pixels[start..start + size[0]].copy_from_slice(patch);
+                    }
+                }
+            }
+        }
+
+        for id in &delta.free {
+            self.textures.remove(id);
+        }
+    }
+}
+
+struct Texture {
+    size: [usize; 2],
+    /// Premultiplied, row by row
+    pixels: Vec<Color32>,
+    filter: TextureFilter,
+}
+
+impl Texture {
+    /// Premultiplied color at texture coordinate `uv`, clamped to the edges
+    fn sample(&self, uv: Pos2) -> [f32; 4] {
+        let [width, height] = self.size;
+        let texel = |x: isize, y: isize| {
+            let x = x.clamp(0, width as isize - 1) as usize;
+            let y = y.clamp(0, height as isize - 1) as usize;
+
+            rgba(self.pixels[y * width + x])
+        };
+
+        let x = uv.x * width as f32 - 0.5;
+        let y = uv.y * height as f32 - 0.5;
+
+        match self.filter {
+            TextureFilter::Nearest => texel(x.round() as isize, y.round() as isize),
+            TextureFilter::Linear => {
+                let (x0, y0) = (x.floor(), y.floor());
+                let (fx, fy) = (x - x0, y - y0);
+                let (x0, y0) = (x0 as isize, y0 as isize);
+
+                let top = lerp(texel(x0, y0), texel(x0 + 1, y0), fx);
+                let bottom = lerp(texel(x0, y0 + 1), texel(x0 + 1, y0 + 1), fx);
+
+                lerp(top, bottom, fy)
+            }
+        }
+    }
+}
+
+/// Premultiplied pixels in gamma space, as blended by the egui backends
+struct Canvas {
+    size: [u32; 2],
+    pixels: Vec<[f32; 4]>,
+}
+
+impl Canvas {
+    fn new(size: [u32; 2]) -> Self {
+        Self {
+            size,
+            pixels: vec![[0.0; 4]; size[0] as usize * size[1] as usize],
+        }
+    }
+
+    /// Rasterize `mesh` within `clip`, both in points
+    fn paint(&mut self, mesh: &Mesh, clip: Rect, pixels_per_point: f32, texture: &Texture) {
+        let clip = clip * pixels_per_point;
+        let clip = Rect::from_min_max(
+            clip.min.max(Pos2::ZERO),
+            clip.max
+                .min(Pos2::new(self.size[0] as f32, self.size[1] as f32)),
+        );
+        if !clip.is_positive() {
+            return;
+        }
+
+        for triangle in mesh.indices.chunks_exact(3) {
+            let [a, b, c] = [0, 1, 2].map(|i| {
+                let vertex = mesh.vertices[triangle[i] as usize];
+
+                Vertex {
+                    pos: (vertex.pos.to_vec2() * pixels_per_point).to_pos2(),
+                    ..vertex
+                }
+            });
+            let area = edge(a.pos, b.pos, c.pos);
+            if area == 0.0 {
+                continue;
+            }
+            // egui does not wind its triangles consistently
+            let (b, c) = if area > 0.0 { (b, c) } else { (c, b) };
+            let area = area.abs();
+
+            let bounds = Rect::from_points(&[a.pos, b.pos, c.pos]).intersect(clip);
+            if !bounds.is_positive() {
+                continue;
+            }
+
+            for y in bounds.min.y.floor() as u32..bounds.max.y.ceil() as u32 {
+                for x in bounds.min.x.floor() as u32..bounds.max.x.ceil() as u32 {
+                    let center = Pos2::new(x as f32 + 0.5, y as f32 + 0.5);
+                    if !clip.contains(center) {
+                        continue;
+                    }
+
+                    let weights = [(b.pos, c.pos), (c.pos, a.pos), (a.pos, b.pos)]
+                        .map(|(from, to)| (edge(from, to, center), to - from));
+                    if !weights
+                        .iter()
+                        .all(|&(weight, direction)| covers(weight, direction))
+                    {
+                        continue;
+                    }
+                    let [wa, wb, wc] = weights.map(|(weight, _)| weight / area);
+
+                    let uv = Pos2::new(
+                        wa * a.uv.x + wb * b.uv.x + wc * c.uv.x,
+                        wa * a.uv.y + wb * b.uv.y + wc * c.uv.y,
+                    );
+                    let [ca, cb, cc] = [a.color, b.color, c.color].map(rgba);
+                    let color: [f32; 4] =
+                        std::array::from_fn(|i| wa * ca[i] + wb * cb[i] + wc * cc[i]);
+                    let texel = texture.sample(uv);
+
+                    let pixel = &mut self.pixels[(y * self.size[0] + x) as usize];
+                    let source: [f32; 4] = std::array::from_fn(|i| color[i] * texel[i]);
+                    for i in 0..4 {
+                        pixel[i] = source[i] + pixel[i] * (1.0 - source[3]);
+                    }
+                }
+            }
+        }
+    }
+
+    fn into_image(self) -> RgbaImage {
+        let mut image = RgbaImage::new(self.size[0], self.size[1]);
+
+        for (pixel, [r, g, b, a]) in image.pixels_mut().zip(self.pixels) {
+            let unmultiply = |channel: f32| {
+                if a > 0.0 {
+                    (channel / a * 255.0).round().clamp(0.0, 255.0) as u8
+                } else {
+                    0
+                }
+            };
+
+            pixel.0 = [
+                unmultiply(r),
+                unmultiply(g),
+                unmultiply(b),
+                (a * 255.0).round().clamp(0.0, 255.0) as u8,
+            ];
+        }
+
+        image
+    }
+}
+
+/// Twice the signed area of the triangle `from`, `to`, `point`
+fn edge(from: Pos2, to: Pos2, point: Pos2) -> f32 {
+    (to.x - from.x) * (point.y - from.y) - (to.y - from.y) * (point.x - from.x)
+}
+
+/// Whether a pixel center at `weight` from an edge running along `direction` is inside
+///
+/// Centers exactly on an edge belong to only one of the two triangles sharing it, so
+/// translucent meshes are not blended twice along their internal edges.
+fn covers(weight: f32, direction: Vec2) -> bool {
+    weight > 0.0
+        || (weight == 0.0 && (direction.y < 0.0 || (direction.y == 0.0 && direction.x > 0.0)))
+}
+
+fn rgba(color: Color32) -> [f32; 4] {
+    color.to_array().map(|channel| f32::from(channel) / 255.0)
+}
+
+fn lerp(a: [f32; 4], b: [f32; 4], t: f32) -> [f32; 4] {
+    std::array::from_fn(|i| a[i] + (b[i] - a[i]) * t)
+}
@@ -0,0 +1,28 @@
+//! The egui instruments of Flock, usable by any egui application
+//!
+//! Every instrument is a [`Widget`](egui::Widget) built from the values it shows, with optional
+//! builder methods for the rest:
+//!
+//! ```no_run
+//! # fn show(ui: &mut egui::Ui) {
+//! use flock_instruments::{AttitudeIndicator, Validity};
+//!
+//! ui.add(AttitudeIndicator::new(10.0, -5.0).validity(Validity::Valid));
+//! # }
+//! ```
+
+pub mod attitude;
+pub mod heading;
+pub mod headless;
+pub mod map;
+pub mod pfd;
+pub mod qr_code;
+pub mod validity;
+
+pub use attitude::{AttitudeIndicator, AttitudeIndicatorRectangular};
+pub use heading::HeadingIndicator;
+pub use headless::HeadlessRenderer;
+pub use map::{Map, MapMarker, MapState};
+pub use pfd::PrimaryFlightDisplay;
+pub use qr_code::QrCode;
+pub use validity::Validity;
@@ -0,0 +1,391 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    f64::consts::PI,
+    fs,
+    path::{Path, PathBuf},
+    sync::mpsc,
+    thread,
+    time::{Duration, Instant},
+};
+
+use egui::{
+    emath::Align2,
+    epaint::{Color32, ColorImage, FontId, Hsva, Pos2, Rect, Rounding, Shape, Stroke, Vec2},
+    Context, Sense, TextureHandle, TextureOptions, Widget,
+};
+use flock_telemetry::telemetry::Position;
+use tracing::{debug, warn};
+
+use crate::heading::heading_arrow;
+
+/// Size of a tile on screen, in points
+const TILE_SIZE: f32 = 256.0;
+const MIN_ZOOM: u8 = 2;
+const MAX_ZOOM: u8 = 19;
+/// Tiles kept in memory before the cache is flushed
+const MAX_CACHED_TILES: usize = 512;
+
+/// Scroll distance that changes the zoom by one level, in points, about a notch of a mouse wheel
+const SCROLL_PER_ZOOM: f32 = 50.0;
+
+/// Zoom and position of the map, kept across frames
+pub struct MapState {
+    zoom: u8,
+    /// Scrolled distance not yet turned into a zoom level, so trackpads zoom smoothly
+    scroll: f32,
+    /// Where the user panned to, following the selected vehicle when `None`
+    center: Option<Position>,
+    tiles: TileCache,
+}
+
+impl MapState {
+    /// A map drawing raster tiles from a `z/x/y.png` directory, if any
+    pub fn new(tiles: Option<PathBuf>) -> Self {
+        Self {
+            zoom: 16,
+            scroll: 0.0,
+            center: None,
+            tiles: TileCache {
+                directory: tiles,
+                tiles: HashMap::new(),
+                loader: None,
+            },
+        }
+    }
+
+    /// Wait up to `timeout` for the tiles still loading, for renders that get no next frame
+    ///
+    /// Returns whether any tile arrived, and so whether the map should be drawn again.
+    pub fn wait_for_tiles(&mut self, ctx: &Context, timeout: Duration) -> bool {
+        self.tiles.wait(ctx, timeout)
+    }
+}
+
+/// Zoom level and coordinates of a tile
+type TileKey = (u8, u32, u32);
+
+enum Tile {
+    /// Read and decoded by the [`TileLoader`] in the background
+    Loading,
+    Loaded(TextureHandle),
+    /// Missing or undecodable, cached so it is only tried once
+    Missing,
+}
+
+/// Offline slippy map tiles, loaded from disk on first use
+struct TileCache {
+    directory: Option<PathBuf>,
+    tiles: HashMap<TileKey, Tile>,
+    /// Started with the first tile requested
+    loader: Option<TileLoader>,
+}
+
+/// Thread reading and decoding tiles, so the UI does not stall on the disk or PNG decoder
+struct TileLoader {
+    requests: mpsc::Sender<(TileKey, PathBuf)>,
+    decoded: mpsc::Receiver<(TileKey, Option<ColorImage>)>,
+}
+
+impl TileLoader {
+    fn start(ctx: &Context) -> Self {
+        let (requests, requested) = mpsc::channel::<(TileKey, PathBuf)>();
+        let (loaded, decoded) = mpsc::channel();
+
+        thread::spawn({
+            let ctx = ctx.clone();
+
+            // Ends once the cache, and with it the sender of requests, is dropped
+            move || {
+                for (key, path) in requested {
+                    if loaded.send((key, decode_tile(&path))).is_err() {
+                        return;
+                    }
+                    ctx.request_repaint();
+                }
+            }
+        });
+
+        Self { requests, decoded }
+    }
+}
+
+impl TileCache {
+    /// The tile at `zoom`, `x` and `y`, requesting it from the loader on first use
+    fn get(&mut self, ctx: &Context, zoom: u8, x: u32, y: u32) -> Option<&Tile> {
+        let directory = self.directory.as_ref()?;
+
+        if self.tiles.len() >= MAX_CACHED_TILES && !self.tiles.contains_key(&(zoom, x, y)) {
+            debug!("flushing map tile cache");
+            self.tiles.clear();
+        }
+
+        let loader = self.loader.get_or_insert_with(|| TileLoader::start(ctx));
+        let tile = self.tiles.entry((zoom, x, y)).or_insert_with(|| {
+            let path = directory.join(format!("{zoom}/{x}/{y}.png"));
+
+            match loader.requests.send(((zoom, x, y), path)) {
+                Ok(()) => Tile::Loading,
+                Err(_) => Tile::Missing,
+            }
+        });
+
+        Some(tile)
+    }
+
+    /// Turn the tiles the loader decoded since the last frame into textures
+    fn receive(&mut self, ctx: &Context) {
+        let Some(loader) = &self.loader else {
+            return;
+        };
+
+        for (key, image) in loader.decoded.try_iter() {
+            self.tiles.insert(key, Self::texture(ctx, key, image));
+        }
+    }
+
+    /// Block until every tile being loaded arrived or `timeout` passed, returning whether any did
+    fn wait(&mut self, ctx: &Context, timeout: Duration) -> bool {
+        let Some(loader) = &self.loader else {
+            return false;
+        };
+
+        let deadline = Instant::now() + timeout;
+        let mut arrived = false;
+        while self
+            .tiles
+            .values()
+            .any(|tile| matches!(tile, Tile::Loading))
+        {
+            let Ok((key, image)) = loader
+                .decoded
+                .recv_timeout(deadline.saturating_duration_since(Instant::now()))
+            else {
+                break;
+            };
+
+            self.tiles.insert(key, Self::texture(ctx, key, image));
+            arrived = true;
+        }
+
+        arrived
+    }
+
+    fn texture(ctx: &Context, (zoom, x, y): TileKey, image: Option<ColorImage>) -> Tile {
+        match image {
+            Some(image) => Tile::Loaded(ctx.load_texture(
+                format!("tile/{zoom}/{x}/{y}"),
+                image,
+                TextureOptions::LINEAR,
+            )),
+            None => Tile::Missing,
+        }
+    }
+}
+
+/// Read and decode the tile at `path`, `None` if it is missing or invalid
+fn decode_tile(path: &Path) -> Option<ColorImage> {
+    let bytes = fs::read(path).ok()?;
+
+    let image = image::load_from_memory(&bytes)
+        .inspect_err(|error| warn!(%error, path = %path.display(), "invalid map tile"))
+        .ok()?
+        .into_rgba8();
+
+    Some(ColorImage::from_rgba_unmultiplied(
+        [image.width() as usize, image.height() as usize],
+        image.as_raw(),
+    ))
+}
+
+/// A vehicle drawn on the map
+pub struct MapMarker<'a> {
+    pub label: &'a str,
+    pub position: Position,
+    pub heading: Option<f32>,
+    /// Breadcrumb trail, oldest first
+    pub track: &'a VecDeque<Position>,
+    pub selected: bool,
+}
+
+/// Web Mercator map of the flock, with the position, heading and track of every vehicle
+pub struct Map<'a> {
+    state: &'a mut MapState,
+    markers: Vec<MapMarker<'a>>,
+}
+
+impl<'a> Map<'a> {
+    pub fn new(state: &'a mut MapState, markers: Vec<MapMarker<'a>>) -> Self {
+        Self { state, markers }
+    }
+}
+
+impl Widget for Map<'_> {
+    fn ui(self, ui: &mut egui::Ui) -> egui::Response {
+        let (response, painter) = ui.allocate_painter(ui.available_size(), Sense::click_and_drag());
+        let bounds = response.rect;
+        let state = self.state;
+
+        if response.hovered() {
+            state.scroll += ui.input(|input| input.raw_scroll_delta.y);
+
+            let levels = (state.scroll / SCROLL_PER_ZOOM).trunc();
+            state.scroll -= levels * SCROLL_PER_ZOOM;
+            state.zoom = (i32::from(state.zoom) + levels as i32)
+                .clamp(i32::from(MIN_ZOOM), i32::from(MAX_ZOOM)) as u8;
+        } else {
+            state.scroll = 0.0;
+        }
+
+        let followed = self
+            .markers
+            .iter()
+            .find(|marker| marker.selected)
+            .or(self.markers.first())
+            .map(|marker| marker.position);
+        let center = state.center.or(followed).unwrap_or_default();
+        let mut center = project(center, state.zoom);
+
+        if response.dragged() {
+            let delta = response.drag_delta();
+            center = [
+                center[0] - f64::from(delta.x),
+                center[1] - f64::from(delta.y),
+            ];
+            state.center = Some(unproject(center, state.zoom));
+        }
+        if response.double_clicked() {
+            state.center = None;
+        }
+
+        // Offsets are taken in f64, world coordinates at high zoom levels do not fit an f32
+        let to_screen = |position: Position| {
+            let [x, y] = project(position, state.zoom);
+
+            bounds.center() + Vec2::new((x - center[0]) as f32, (y - center[1]) as f32)
+        };
+
+        painter.rect_filled(bounds, Rounding::ZERO, Hsva::new(0.0, 0.0, 0.1, 1.0));
+
+        // Tiles
+        state.tiles.receive(ui.ctx());
+        let tiles = 1_i64 << state.zoom;
+        let tile_size = f64::from(TILE_SIZE);
+        let left = center[0] - f64::from(bounds.width()) / 2.0;
+        let top = center[1] - f64::from(bounds.height()) / 2.0;
+
+        for y in (top / tile_size).floor() as i64
+            ..=((top + f64::from(bounds.height())) / tile_size).floor() as i64
+        {
+            if y < 0 || y >= tiles {
+                continue;
+            }
+
+            for x in (left / tile_size).floor() as i64
+                ..=((left + f64::from(bounds.width())) / tile_size).floor() as i64
+            {
+                let rect = Rect::from_min_size(
+                    bounds.min
+                        + Vec2::new(
+                            (x as f64 * tile_size - left) as f32,
+                            (y as f64 * tile_size - top) as f32,
+                        ),
+                    Vec2::splat(TILE_SIZE),
+                );
+                // The map wraps around horizontally
+                let x = x.rem_euclid(tiles) as u32;
+
+                match state.tiles.get(ui.ctx(), state.zoom, x, y as u32) {
+                    Some(Tile::Loaded(texture)) => {
+                        painter.image(
+                            texture.id(),
+                            rect,
+                            Rect::from_min_max(Pos2::ZERO, Pos2::new(1.0, 1.0)),
+                            Color32::WHITE,
+                        );
+                    }
+                    Some(Tile::Loading) => {
+                        painter.rect_filled(
+                            rect.shrink(1.0),
+                            Rounding::ZERO,
+                            Hsva::new(0.0, 0.0, 0.15, 1.0),
+                        );
+                    }
+                    Some(Tile::Missing) | None => {
+                        painter.rect_stroke(
+                            rect,
+                            Rounding::ZERO,
+                            Stroke::new(1.0, Hsva::new(0.0, 0.0, 0.2, 1.0)),
+                        );
+                    }
+                }
+            }
+        }
+
+        // Tracks below every marker
+        for marker in &self.markers {
+            painter.add(Shape::line(
+                marker.track.iter().copied().map(to_screen).collect(),
+                Stroke::new(2.0, Hsva::new(0.15, 0.9, 0.9, 0.6)),
+            ));
+        }
+
+        let font = FontId::proportional(14.0);
+        for marker in &self.markers {
+            let position = to_screen(marker.position);
+
+            if marker.selected {
+                painter.circle_stroke(position, 24.0, Stroke::new(2.0, Color32::WHITE));
+            }
+
+            match marker.heading {
+                Some(heading) => painter.extend(heading_arrow(position, heading, 30.0, 2.0)),
+                None => {
+                    painter.circle_filled(position, 6.0, Hsva::new(0.0, 1.0, 0.8, 1.0));
+                }
+            }
+
+            painter.text(
+                position + Vec2::new(28.0, 0.0),
+                Align2::LEFT_CENTER,
+                marker.label,
+                font.clone(),
+                Color32::WHITE,
+            );
+        }
+
+        let caption = match (&state.tiles.directory, state.center) {
+            (None, _) => format!("zoom {}, no tile directory configured", state.zoom),
+            (Some(_), None) => format!("zoom {}, following", state.zoom),
+            (Some(_), Some(_)) => format!("zoom {}, double click to follow", state.zoom),
+        };
+        painter.text(
+            bounds.left_bottom() + Vec2::new(4.0, -4.0),
+            Align2::LEFT_BOTTOM,
+            caption,
+            font,
+            Color32::WHITE,
+        );
+
+        response.on_hover_text("Drag to pan, scroll to zoom")
+    }
+}
+
+/// Web Mercator projection to points at `zoom`, relative to the top left of the world
+fn project(position: Position, zoom: u8) -> [f64; 2] {
+    let size = f64::from(TILE_SIZE) * f64::from(1_u32 << zoom);
+    let latitude = position.latitude.clamp(-85.0511, 85.0511).to_radians();
+
+    let x = (position.longitude + 180.0) / 360.0;
+    let y = (1.0 - latitude.tan().asinh() / PI) / 2.0;
+
+    [x * size, y * size]
+}
+
+fn unproject([x, y]: [f64; 2], zoom: u8) -> Position {
+    let size = f64::from(TILE_SIZE) * f64::from(1_u32 << zoom);
+
+    Position {
+        latitude: (PI * (1.0 - 2.0 * y / size)).sinh().atan().to_degrees(),
+        longitude: x / size * 360.0 - 180.0,
+    }
+}
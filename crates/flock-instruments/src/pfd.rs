@@ -0,0 +1,383 @@
+use egui::{
+    emath::Align2,
+    epaint::{Color32, FontId, Hsva, Pos2, Rect, Rounding, Shape, Stroke, Vec2},
+    Painter, Sense, Widget,
+};
+use flock_telemetry::telemetry::Telemetry;
+
+use crate::{attitude::AttitudeIndicatorRectangular, validity::Validity};
+
+/// Width over height of the whole display
+const ASPECT_RATIO: f32 = 4.0 / 3.0;
+
+/// Attitude, airspeed, altitude, vertical speed and heading in a single display
+pub struct PrimaryFlightDisplay {
+    heading: Option<f32>,
+    /// Pitch and roll, which only make up an attitude together
+    attitude: Option<(f32, f32)>,
+    airspeed: Option<f32>,
+    altitude: Option<f32>,
+    vertical_speed: Option<f32>,
+    validity: Validity,
+}
+
+impl PrimaryFlightDisplay {
+    pub fn new(telemetry: &Telemetry) -> Self {
+        Self {
+            heading: telemetry.heading,
+            attitude: telemetry.pitch.zip(telemetry.roll),
+            airspeed: telemetry.airspeed,
+            altitude: telemetry.altitude,
+            vertical_speed: telemetry.vertical_speed,
+            validity: Validity::Valid,
+        }
+    }
+
+    pub fn validity(mut self, validity: Validity) -> Self {
+        self.validity = validity;
+        self
+    }
+}
+
+impl Widget for PrimaryFlightDisplay {
+    fn ui(self, ui: &mut egui::Ui) -> egui::Response {
+        let space = ui.available_size();
+        let size = if space.x / space.y > ASPECT_RATIO {
+            Vec2::new(space.y * ASPECT_RATIO, space.y)
+        } else {
+            Vec2::new(space.x, space.x / ASPECT_RATIO)
+        };
+        let (response, painter) = ui.allocate_painter(size, Sense::focusable_noninteractive());
+        let bounds = response.rect;
+
+        let unit = bounds.height();
+        let heading_height = unit * 0.12;
+        let tape_width = unit * 0.18;
+        let vsi_width = unit * 0.08;
+
+        let top = bounds.top();
+        let bottom = bounds.bottom() - heading_height;
+
+        let airspeed = Rect::from_min_max(
+            Pos2::new(bounds.left(), top),
+            Pos2::new(bounds.left() + tape_width, bottom),
+        );
+        let vsi = Rect::from_min_max(
+            Pos2::new(bounds.right() - vsi_width, top),
+            Pos2::new(bounds.right(), bottom),
+        );
+        let altitude = Rect::from_min_max(
+            Pos2::new(vsi.left() - tape_width, top),
+            Pos2::new(vsi.left(), bottom),
+        );
+        let horizon = Rect::from_min_max(
+            Pos2::new(airspeed.right(), top),
+            Pos2::new(altitude.left(), bottom),
+        );
+        let heading = Rect::from_min_max(
+            Pos2::new(bounds.left(), bottom),
+            Pos2::new(bounds.right(), bounds.bottom()),
+        );
+
+        match self.attitude {
+            Some((pitch, roll)) => {
+                AttitudeIndicatorRectangular::new(pitch, roll).paint(&painter, horizon);
+            }
+            None => {
+                painter.rect_filled(horizon, Rounding::ZERO, background());
+                Validity::Missing.paint(&painter, horizon);
+            }
+        }
+
+        paint_vertical_tape(
+            &painter,
+            airspeed,
+            &Tape {
+                label: "IAS m/s",
+                value: self.airspeed,
+                span: 40.0,
+                minor: 2.0,
+                major: 10.0,
+                minimum: Some(0.0),
+            },
+            Align2::RIGHT_CENTER,
+        );
+        paint_vertical_tape(
+            &painter,
+            altitude,
+            &Tape {
+                label: "ALT m",
+                value: self.altitude,
+                span: 200.0,
+                minor: 10.0,
+                major: 50.0,
+                minimum: None,
+            },
+            Align2::LEFT_CENTER,
+        );
+        paint_vertical_speed(&painter, vsi, self.vertical_speed);
+        paint_heading_tape(
+            &painter,
+            heading,
+            self.heading.map(|heading| heading.rem_euclid(360.0)),
+        );
+
+        self.validity.paint(&painter, bounds);
+
+        response
+    }
+}
+
+fn background() -> Color32 {
+    Hsva::new(0.0, 0.0, 0.02, 0.85).into()
+}
+
+fn foreground() -> Color32 {
+    Color32::WHITE
+}
+
+/// Shown instead of a value the vehicle does not report
+fn invalid() -> Color32 {
+    Hsva::new(0.1, 0.9, 0.9, 1.0).into()
+}
+
+struct Tape {
+    label: &'static str,
+    value: Option<f32>,
+    /// Range of values visible on the tape at once
+    span: f32,
+    /// Distance between ticks
+    minor: f32,
+    /// Distance between labelled ticks
+    major: f32,
+    /// Values below this are not drawn, like negative airspeeds
+    minimum: Option<f32>,
+}
+
+/// A scale scrolling past a fixed readout box, with the ticks on the side of `ticks`
+fn paint_vertical_tape(painter: &Painter, bounds: Rect, tape: &Tape, ticks: Align2) {
+    let painter = painter.with_clip_rect(bounds.intersect(painter.clip_rect()));
+    let stroke = Stroke::new(bounds.width() * 0.02, foreground());
+    let font = FontId::monospace(bounds.width() * 0.16);
+
+    painter.rect_filled(bounds, Rounding::ZERO, background());
+    painter.text(
+        bounds.center_top() + Vec2::Y * bounds.width() * 0.05,
+        Align2::CENTER_TOP,
+        tape.label,
+        font.clone(),
+        foreground(),
+    );
+
+    let Some(value) = tape.value else {
+        painter.text(
+            bounds.center(),
+            Align2::CENTER_CENTER,
+            "---",
+            font,
+            invalid(),
+        );
+        return;
+    };
+
+    let pixels_per_unit = bounds.height() / tape.span;
+    let edge = if ticks == Align2::RIGHT_CENTER {
+        bounds.right()
+    } else {
+        bounds.left()
+    };
+    let inwards = if ticks == Align2::RIGHT_CENTER {
+        -1.0
+    } else {
+        1.0
+    };
+
+    let first = ((value - tape.span / 2.0) / tape.minor).floor() as i32;
+    let last = ((value + tape.span / 2.0) / tape.minor).ceil() as i32;
+
+    for tick in first..=last {
+        let tick_value = tick as f32 * tape.minor;
+
+        if tape.minimum.is_some_and(|minimum| tick_value < minimum) {
+            continue;
+        }
+
+        let y = bounds.center().y - (tick_value - value) * pixels_per_unit;
+        let is_major = (tick_value / tape.major).round() * tape.major == tick_value;
+        let length = bounds.width() * if is_major { 0.2 } else { 0.1 };
+
+        painter.line_segment(
+            [Pos2::new(edge, y), Pos2::new(edge + inwards * length, y)],
+            stroke,
+        );
+
+        if is_major {
+            painter.text(
+                Pos2::new(edge + inwards * bounds.width() * 0.3, y),
+                ticks,
+                format!("{tick_value:.0}"),
+                font.clone(),
+                foreground(),
+            );
+        }
+    }
+
+    // Readout box
+    let readout = Rect::from_center_size(
+        bounds.center(),
+        Vec2::new(bounds.width() * 0.9, bounds.width() * 0.3),
+    );
+    painter.rect(readout, Rounding::same(2.0), Color32::BLACK, stroke);
+    painter.text(
+        readout.center(),
+        Align2::CENTER_CENTER,
+        format!("{value:.0}"),
+        FontId::monospace(bounds.width() * 0.22),
+        foreground(),
+    );
+}
+
+/// Vertical speed in m/s, on a linear scale of ±10 m/s
+fn paint_vertical_speed(painter: &Painter, bounds: Rect, vertical_speed: Option<f32>) {
+    const RANGE: f32 = 10.0;
+
+    let painter = painter.with_clip_rect(bounds.intersect(painter.clip_rect()));
+    let stroke = Stroke::new(bounds.width() * 0.04, foreground());
+    let font = FontId::monospace(bounds.width() * 0.3);
+
+    painter.rect_filled(bounds, Rounding::ZERO, background());
+
+    let scale = bounds.shrink2(Vec2::new(0.0, bounds.height() * 0.1));
+    let y =
+        |speed: f32| scale.center().y - speed.clamp(-RANGE, RANGE) / RANGE * scale.height() / 2.0;
+
+    for speed in [-10.0, -5.0, -2.0, 0.0, 2.0, 5.0, 10.0] {
+        let is_labelled = f32::abs(speed) == 5.0 || f32::abs(speed) == 10.0;
+
+        painter.line_segment(
+            [
+                Pos2::new(bounds.left(), y(speed)),
+                Pos2::new(
+                    bounds.left() + bounds.width() * if is_labelled { 0.3 } else { 0.15 },
+                    y(speed),
+                ),
+            ],
+            stroke,
+        );
+
+        if is_labelled {
+            painter.text(
+                Pos2::new(bounds.left() + bounds.width() * 0.4, y(speed)),
+                Align2::LEFT_CENTER,
+                format!("{:.0}", f32::abs(speed)),
+                font.clone(),
+                foreground(),
+            );
+        }
+    }
+
+    let Some(vertical_speed) = vertical_speed else {
+        painter.text(bounds.center(), Align2::CENTER_CENTER, "-", font, invalid());
+        return;
+    };
+
+    painter.line_segment(
+        [
+            Pos2::new(bounds.right(), scale.center().y),
+            Pos2::new(bounds.left(), y(vertical_speed)),
+        ],
+        Stroke::new(bounds.width() * 0.06, Color32::GREEN),
+    );
+    painter.text(
+        bounds.center_top() + Vec2::Y * bounds.height() * 0.02,
+        Align2::CENTER_TOP,
+        format!("{vertical_speed:+.1}"),
+        font,
+        foreground(),
+    );
+}
+
+/// Horizontal compass tape, 60° wide
+fn paint_heading_tape(painter: &Painter, bounds: Rect, heading: Option<f32>) {
+    const SPAN: f32 = 60.0;
+
+    let painter = painter.with_clip_rect(bounds.intersect(painter.clip_rect()));
+    let stroke = Stroke::new(bounds.height() * 0.03, foreground());
+    let font = FontId::monospace(bounds.height() * 0.28);
+
+    painter.rect_filled(bounds, Rounding::ZERO, background());
+
+    let Some(heading) = heading else {
+        painter.text(
+            bounds.center(),
+            Align2::CENTER_CENTER,
+            "---",
+            font,
+            invalid(),
+        );
+        return;
+    };
+
+    let pixels_per_degree = bounds.width() / SPAN;
+    let first = ((heading - SPAN / 2.0) / 5.0).floor() as i32;
+    let last = ((heading + SPAN / 2.0) / 5.0).ceil() as i32;
+
+    for tick in first..=last {
+        let degree = tick * 5;
+        let x = bounds.center().x + (degree as f32 - heading) * pixels_per_degree;
+        let is_labelled = degree % 10 == 0;
+
+        painter.line_segment(
+            [
+                Pos2::new(x, bounds.top()),
+                Pos2::new(
+                    x,
+                    bounds.top() + bounds.height() * if is_labelled { 0.3 } else { 0.15 },
+                ),
+            ],
+            stroke,
+        );
+
+        if is_labelled {
+            let degree = degree.rem_euclid(360);
+            let label = match degree {
+                0 => "N".to_owned(),
+                90 => "E".to_owned(),
+                180 => "S".to_owned(),
+                270 => "W".to_owned(),
+                degree => format!("{:02}", degree / 10),
+            };
+
+            painter.text(
+                Pos2::new(x, bounds.top() + bounds.height() * 0.35),
+                Align2::CENTER_TOP,
+                label,
+                font.clone(),
+                foreground(),
+            );
+        }
+    }
+
+    // Lubber line and readout
+    painter.add(Shape::convex_polygon(
+        vec![
+            bounds.center_top() + Vec2::new(0.0, bounds.height() * 0.2),
+            bounds.center_top() + Vec2::new(bounds.height() * 0.1, 0.0),
+            bounds.center_top() + Vec2::new(-bounds.height() * 0.1, 0.0),
+        ],
+        Color32::YELLOW,
+        Stroke::NONE,
+    ));
+    let readout = Rect::from_center_size(
+        bounds.center_bottom() - Vec2::Y * bounds.height() * 0.2,
+        Vec2::new(bounds.height() * 1.2, bounds.height() * 0.4),
+    );
+    painter.rect(readout, Rounding::same(2.0), Color32::BLACK, stroke);
+    painter.text(
+        readout.center(),
+        Align2::CENTER_CENTER,
+        format!("{:03.0}°", heading.round().rem_euclid(360.0)),
+        font,
+        foreground(),
+    );
+}
@@ -0,0 +1,59 @@
+use egui::{
+    epaint::{Color32, Rect, Shape, Vec2},
+    Sense, Widget,
+};
+
+/// Modules of white border around the code, as required by the spec
+const QUIET_ZONE: usize = 4;
+
+pub struct QrCode {
+    code: Option<qrcode::QrCode>,
+    size: f32,
+}
+
+impl QrCode {
+    pub fn new(data: &str, size: f32) -> Self {
+        Self {
+            code: qrcode::QrCode::new(data).ok(),
+            size,
+        }
+    }
+}
+
+impl Widget for QrCode {
+    fn ui(self, ui: &mut egui::Ui) -> egui::Response {
+        let (response, painter) = ui.allocate_painter(Vec2::splat(self.size), Sense::hover());
+        let bounds = response.rect;
+
+        let Some(code) = self.code else {
+            painter.rect_filled(bounds, 0.0, Color32::DARK_RED);
+            return response.on_hover_text("Data too long for a QR code");
+        };
+
+        let modules = code.width() + 2 * QUIET_ZONE;
+        let module_size = bounds.width() / modules as f32;
+
+        painter.add(Shape::rect_filled(bounds, 0.0, Color32::WHITE));
+        painter.extend(
+            code.to_colors()
+                .into_iter()
+                .enumerate()
+                .filter(|(_, color)| *color == qrcode::Color::Dark)
+                .map(|(index, _)| {
+                    let x = index % code.width() + QUIET_ZONE;
+                    let y = index / code.width() + QUIET_ZONE;
+
+                    Shape::rect_filled(
+                        Rect::from_min_size(
+                            bounds.min + Vec2::new(x as f32, y as f32) * module_size,
+                            Vec2::splat(module_size),
+                        ),
+                        0.0,
+                        Color32::BLACK,
+                    )
+                }),
+        );
+
+        response
+    }
+}
@@ -0,0 +1,67 @@
+use std::time::Duration;
+
+use egui::{
+    emath::Align2,
+    epaint::{Color32, FontId, Rect, Rounding, Stroke, Vec2},
+    Painter,
+};
+
+/// Whether the data an instrument shows is current, drawn over the instrument when it is not
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum Validity {
+    #[default]
+    Valid,
+    /// The source is still connected, but the newest data is this old
+    Stale(Duration),
+    /// The source is gone, the instrument shows the last values received
+    Disconnected,
+    /// The source never reported what the instrument shows, so it shows nothing
+    Missing,
+}
+
+impl Validity {
+    /// Grey out the instrument in `bounds` and flag why, unless the data is valid
+    pub fn paint(self, painter: &Painter, bounds: Rect) {
+        let (flag, color) = match self {
+            Validity::Valid => return,
+            Validity::Stale(age) => (
+                format!("NO DATA {:.0} s", age.as_secs_f32()),
+                Color32::from_rgb(255, 191, 0),
+            ),
+            Validity::Disconnected => ("NO LINK".to_owned(), Color32::RED),
+            Validity::Missing => ("NOT REPORTED".to_owned(), Color32::from_rgb(255, 191, 0)),
+        };
+
+        let painter = painter.with_clip_rect(bounds.intersect(painter.clip_rect()));
+        let size = f32::min(bounds.width(), bounds.height());
+
+        // There is nothing behind a missing value worth seeing through
+        let shade = if self == Validity::Missing { 255 } else { 160 };
+        painter.rect_filled(bounds, Rounding::ZERO, Color32::from_black_alpha(shade));
+
+        // The conventional red cross over an instrument that has failed
+        if self == Validity::Disconnected {
+            let stroke = Stroke::new(size * 0.02, color);
+
+            painter.line_segment([bounds.left_top(), bounds.right_bottom()], stroke);
+            painter.line_segment([bounds.right_top(), bounds.left_bottom()], stroke);
+        }
+
+        let text = painter.layout(flag, FontId::monospace(size * 0.08), color, f32::INFINITY);
+        let flag = Rect::from_center_size(bounds.center(), text.size() + Vec2::splat(size * 0.04));
+
+        painter.rect(
+            flag,
+            Rounding::same(size * 0.01),
+            Color32::BLACK,
+            Stroke::new(size * 0.01, color),
+        );
+        painter.galley(
+            Align2::CENTER_CENTER
+                .anchor_size(flag.center(), text.size())
+                .min,
+            text,
+            color,
+        );
+    }
+}
@@ -0,0 +1,269 @@
+//! Golden-image tests of the instruments, rendered headlessly and compared with the references
+//! in `tests/snapshots`
+//!
+//! After an intended change to the look of an instrument, bless the new references with
+//!
+//! ```sh
+//! BLESS_SNAPSHOTS=1 cargo test -p flock-instruments --test snapshots
+//! ```
+//!
+//! and review them like any other change. Failed comparisons leave the rendered image and a
+//! diff next to each other in the target directory.
+
+use std::{path::PathBuf, time::Duration};
+
+use egui::Widget;
+use flock_instruments::{
+    AttitudeIndicator, AttitudeIndicatorRectangular, HeadingIndicator, HeadlessRenderer,
+    PrimaryFlightDisplay, Validity,
+};
+use flock_telemetry::telemetry::Telemetry;
+use image::{Rgba, RgbaImage};
+
+const SIZE: [u32; 2] = [200, 200];
+/// The primary flight display is wider, to fit the tapes next to the attitude
+const PFD_SIZE: [u32; 2] = [400, 300];
+/// Channel differences up to this are rounding, not a change
+const CHANNEL_TOLERANCE: u8 = 16;
+/// Fraction of the pixels allowed to differ by more than [`CHANNEL_TOLERANCE`]
+const PIXEL_TOLERANCE: f64 = 0.002;
+
+/// Pitch and roll of the attitude snapshots, named after what they cover
+const ATTITUDES: &[(&str, f32, f32)] = &[
+    ("level", 0.0, 0.0),
+    ("climbing_left", 10.0, -20.0),
+    ("descending_right", -15.0, 45.0),
+    ("nose_up", 90.0, 0.0),
+    ("nose_down", -90.0, 0.0),
+    ("nose_up_rolled", 90.0, 30.0),
+    ("inverted", 0.0, 180.0),
+    ("inverted_negative", 0.0, -180.0),
+    ("inverted_climbing", 20.0, 160.0),
+    ("knife_edge", 0.0, 90.0),
+    ("past_vertical", 120.0, 0.0),
+];
+
+/// Render the widget made by `widget`, which is called once for every pass of the renderer
+fn render<W: Widget>(widget: impl Fn() -> W) -> RgbaImage {
+    render_sized(SIZE, widget)
+}
+
+fn render_sized<W: Widget>(size: [u32; 2], widget: impl Fn() -> W) -> RgbaImage {
+    HeadlessRenderer::new().render(size, 1.0, |ui| {
+        ui.add(widget());
+    })
+}
+
+/// Compare `image` with the reference called `name`, or replace the reference when blessing
+fn check(name: &str, image: &RgbaImage) -> Result<(), String> {
+    let reference_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/snapshots")
+        .join(format!("{name}.png"));
+
+    if std::env::var_os("BLESS_SNAPSHOTS").is_some() {
+        image
+            .save(&reference_path)
+            .map_err(|error| error.to_string())?;
+
+        return Ok(());
+    }
+
+    let failed_path = |suffix: &str| {
+        PathBuf::from(env!("CARGO_TARGET_TMPDIR"))
+            .join("snapshots")
+            .join(format!("{name}.{suffix}.png"))
+    };
+    let save_failure = |diff: Option<RgbaImage>| {
+        let _ = std::fs::create_dir_all(failed_path("").parent().expect("has a parent"));
+        let _ = image.save(failed_path("actual"));
+        if let Some(diff) = diff {
+            let _ = diff.save(failed_path("diff"));
+        }
+    };
+
+    let reference = match image::open(&reference_path) {
+        Ok(reference) => reference.into_rgba8(),
+        Err(error) => {
+            save_failure(None);
+            return Err(format!(
+                "{name}: no reference at {} ({error}), bless it with BLESS_SNAPSHOTS=1",
+                reference_path.display()
+            ));
+        }
+    };
+    if reference.dimensions() != image.dimensions() {
+        save_failure(None);
+        return Err(format!(
+            "{name}: rendered {:?}, the reference is {:?}",
+            image.dimensions(),
+            reference.dimensions()
+        ));
+    }
+
+    let (differing, diff) = compare(image, &reference);
+    let fraction = f64::from(differing) / f64::from(image.width() * image.height());
+    if fraction > PIXEL_TOLERANCE {
+        save_failure(Some(diff));
+        return Err(format!(
+            "{name}: {differing} pixels ({:.2}%) differ, see {}",
+            fraction * 100.0,
+            failed_path("diff").display()
+        ));
+    }
+
+    Ok(())
+}
+
+/// The number of pixels of `image` that differ from `reference`, and an image of them in red
+/// over a faded copy of the reference
+fn compare(image: &RgbaImage, reference: &RgbaImage) -> (u32, RgbaImage) {
+    let mut diff = RgbaImage::new(image.width(), image.height());
+    let mut differing = 0;
+    for ((actual, expected), pixel) in image
+        .pixels()
+        .zip(reference.pixels())
+        .zip(diff.pixels_mut())
+    {
+        let distance = actual
+            .0
+            .iter()
+            .zip(expected.0)
+            .map(|(a, b)| a.abs_diff(b))
+            .max()
+            .unwrap_or_default();
+
+        *pixel = if distance > CHANNEL_TOLERANCE {
+            differing += 1;
+            Rgba([255, 0, 0, 255])
+        } else {
+            let [r, g, b, _] = expected.0.map(|channel| channel / 4);
+            Rgba([r, g, b, 255])
+        };
+    }
+
+    (differing, diff)
+}
+
+/// Check every snapshot, reporting all the failures at once
+fn check_all(snapshots: impl IntoIterator<Item = (String, RgbaImage)>) {
+    let failures = snapshots
+        .into_iter()
+        .filter_map(|(name, image)| check(&name, &image).err())
+        .collect::<Vec<_>>();
+
+    assert!(failures.is_empty(), "\n{}", failures.join("\n"));
+}
+
+#[test]
+fn attitude_indicator() {
+    check_all(ATTITUDES.iter().map(|&(name, pitch, roll)| {
+        (
+            format!("attitude_{name}"),
+            render(|| AttitudeIndicator::new(pitch, roll)),
+        )
+    }));
+}
+
+#[test]
+fn attitude_indicator_rectangular() {
+    check_all(ATTITUDES.iter().map(|&(name, pitch, roll)| {
+        (
+            format!("attitude_rectangular_{name}"),
+            render(|| AttitudeIndicatorRectangular::new(pitch, roll)),
+        )
+    }));
+}
+
+#[test]
+fn heading_indicator() {
+    check_all(
+        [
+            ("north", 0.0),
+            ("east_north_east", 67.5),
+            ("south", 180.0),
+            ("almost_north", 359.0),
+            ("rounding_to_north", 359.6),
+        ]
+        .map(|(name, heading)| {
+            (
+                format!("heading_{name}"),
+                render(|| HeadingIndicator::new(heading)),
+            )
+        }),
+    );
+}
+
+#[test]
+fn heading_wraps() {
+    // Headings outside 0..360 look exactly like the same heading inside it
+    for (heading, wrapped) in [(360.0, 0.0), (-360.0, 0.0), (-1.0, 359.0), (719.0, 359.0)] {
+        let (differing, _) = compare(
+            &render(|| HeadingIndicator::new(heading)),
+            &render(|| HeadingIndicator::new(wrapped)),
+        );
+
+        assert_eq!(differing, 0, "{heading} does not look like {wrapped}");
+    }
+}
+
+#[test]
+fn validity() {
+    check_all([
+        (
+            "attitude_stale".to_owned(),
+            render(|| {
+                AttitudeIndicator::new(10.0, -20.0)
+                    .validity(Validity::Stale(Duration::from_secs(3)))
+            }),
+        ),
+        (
+            "heading_disconnected".to_owned(),
+            render(|| HeadingIndicator::new(67.5).validity(Validity::Disconnected)),
+        ),
+        (
+            "attitude_missing".to_owned(),
+            render(|| AttitudeIndicatorRectangular::new(0.0, 0.0).validity(Validity::Missing)),
+        ),
+    ]);
+}
+
+#[test]
+fn primary_flight_display() {
+    let cruise = Telemetry {
+        heading: Some(359.5),
+        pitch: Some(5.0),
+        roll: Some(-15.0),
+        airspeed: Some(14.2),
+        altitude: Some(35.0),
+        vertical_speed: Some(1.5),
+        ..Default::default()
+    };
+    let inverted = Telemetry {
+        heading: Some(90.0),
+        pitch: Some(-90.0),
+        roll: Some(180.0),
+        ..Default::default()
+    };
+
+    // Connected, but only reporting speeds
+    let no_attitude = Telemetry {
+        airspeed: Some(12.0),
+        roll: Some(10.0),
+        ..Default::default()
+    };
+
+    check_all([
+        (
+            "pfd_no_attitude".to_owned(),
+            render_sized(PFD_SIZE, || PrimaryFlightDisplay::new(&no_attitude)),
+        ),
+        (
+            "pfd_cruise".to_owned(),
+            render_sized(PFD_SIZE, || PrimaryFlightDisplay::new(&cruise)),
+        ),
+        (
+            "pfd_inverted".to_owned(),
+            render_sized(PFD_SIZE, || PrimaryFlightDisplay::new(&inverted)),
+        ),
+    ]);
+}
@@ -0,0 +1,213 @@
+//! Correction of the attitude measured by a sensor that is not mounted square in the airframe
+//!
+//! A calibration consists of the mounting of the sensor, entered by hand, and optionally the
+//! attitude the aircraft had when it was captured as level. Both are applied as rotations of
+//! the body frame, so a sensor mounted sideways still reads the correct pitch and roll
+//! everywhere, not just near level.
+
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    orientation::{Attitude, Quaternion},
+    vehicle::VehicleId,
+};
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Calibration {
+    /// Attitude of the sensor relative to the airframe, a heading of 90° for a sensor facing
+    /// the right wing
+    pub mounting: Attitude,
+    /// The captured "level here" reference
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub level: Option<Level>,
+}
+
+/// Pitch and roll of the airframe, with the mounting corrected for, when it was captured as level
+///
+/// The heading is not part of the reference, it stays relative to north.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Level {
+    pub pitch: f32,
+    pub roll: f32,
+}
+
+impl Level {
+    fn quaternion(self) -> Quaternion {
+        Attitude::new(0.0, self.pitch, self.roll).quaternion()
+    }
+}
+
+impl Calibration {
+    pub fn is_identity(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Attitude of the airframe, from the attitude measured by the sensor
+    pub fn apply(&self, sensor: Attitude) -> Attitude {
+        let body = sensor.quaternion() * self.mounting.quaternion().inverse();
+        let level = self.level.map_or(Quaternion::IDENTITY, Level::quaternion);
+
+        Attitude::from_quaternion(body * level.inverse())
+    }
+
+    /// Take the airframe to be level while the sensor measures `sensor`
+    pub fn capture_level(&mut self, sensor: Attitude) {
+        let body =
+            Attitude::from_quaternion(sensor.quaternion() * self.mounting.quaternion().inverse());
+
+        self.level = Some(Level {
+            pitch: body.pitch,
+            roll: body.roll,
+        });
+    }
+}
+
+/// The calibration of every source, persisted to a TOML file
+#[derive(Debug, Default)]
+pub struct Calibrations {
+    path: Option<PathBuf>,
+    vehicles: BTreeMap<VehicleId, Calibration>,
+}
+
+#[derive(Debug)]
+pub enum CalibrationError {
+    Read(PathBuf, io::Error),
+    Parse(PathBuf, toml::de::Error),
+    Write(PathBuf, io::Error),
+}
+
+impl std::fmt::Display for CalibrationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CalibrationError::Read(path, error) => {
+                write!(f, "failed to read {}: {error}", path.display())
+            }
+            CalibrationError::Parse(path, error) => {
+                write!(f, "failed to parse {}: {error}", path.display())
+            }
+            CalibrationError::Write(path, error) => {
+                write!(f, "failed to write {}: {error}", path.display())
+            }
+        }
+    }
+}
+
+impl std::error::Error for CalibrationError {}
+
+impl Calibrations {
+    /// Load the calibrations saved at `path`, starting without any if it does not exist yet
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, CalibrationError> {
+        let path = path.as_ref();
+
+        let vehicles = match fs::read_to_string(path) {
+            Ok(contents) => toml::from_str(&contents)
+                .map_err(|error| CalibrationError::Parse(path.to_owned(), error))?,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(error) => return Err(CalibrationError::Read(path.to_owned(), error)),
+        };
+
+        Ok(Self {
+            path: Some(path.to_owned()),
+            vehicles,
+        })
+    }
+
+    pub fn get(&self, id: &VehicleId) -> Calibration {
+        self.vehicles.get(id).copied().unwrap_or_default()
+    }
+
+    /// Change the calibration of `id`, until the next [`Calibrations::save`]
+    pub fn set(&mut self, id: &VehicleId, calibration: Calibration) {
+        if calibration.is_identity() {
+            self.vehicles.remove(id);
+        } else {
+            self.vehicles.insert(id.clone(), calibration);
+        }
+    }
+
+    /// Save every calibration to the file they were loaded from
+    pub fn save(&self) -> Result<(), CalibrationError> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let contents = toml::to_string(&self.vehicles)
+            .map_err(|error| CalibrationError::Write(path.clone(), io::Error::other(error)))?;
+
+        fs::write(path, contents).map_err(|error| CalibrationError::Write(path.clone(), error))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_attitude_eq(actual: Attitude, expected: Attitude) {
+        let heading_error = (actual.heading - expected.heading + 180.0).rem_euclid(360.0) - 180.0;
+
+        assert!(
+            heading_error.abs() < 1e-3
+                && (actual.pitch - expected.pitch).abs() < 1e-3
+                && (actual.roll - expected.roll).abs() < 1e-3,
+            "{actual:?} != {expected:?}"
+        );
+    }
+
+    #[test]
+    fn sideways_mount() {
+        let calibration = Calibration {
+            mounting: Attitude::new(90.0, 0.0, 0.0),
+            level: None,
+        };
+
+        // Facing the right wing, the sensor sees the aircraft pitching up as rolling right
+        assert_attitude_eq(
+            calibration.apply(Attitude::new(100.0, 0.0, 20.0)),
+            Attitude::new(10.0, 20.0, 0.0),
+        );
+    }
+
+    #[test]
+    fn level_here() {
+        let mut calibration = Calibration::default();
+        calibration.capture_level(Attitude::new(30.0, 5.0, -3.0));
+
+        assert_attitude_eq(
+            calibration.apply(Attitude::new(30.0, 5.0, -3.0)),
+            Attitude::new(30.0, 0.0, 0.0),
+        );
+
+        // The reference is fixed to the airframe, so it holds in any attitude
+        let body = Attitude::new(210.0, 10.0, 20.0);
+        let sensor = Attitude::from_quaternion(
+            body.quaternion()
+                * Level {
+                    pitch: 5.0,
+                    roll: -3.0,
+                }
+                .quaternion(),
+        );
+        assert_attitude_eq(calibration.apply(sensor), body);
+    }
+
+    #[test]
+    fn level_here_with_mount() {
+        let mut calibration = Calibration {
+            mounting: Attitude::new(90.0, 0.0, 0.0),
+            level: None,
+        };
+        calibration.capture_level(Attitude::new(90.0, 4.0, 2.0));
+
+        // The tilt moves the heading of the airframe slightly, only pitch and roll are zeroed
+        let level = calibration.apply(Attitude::new(90.0, 4.0, 2.0));
+        assert_attitude_eq(level, Attitude::new(level.heading, 0.0, 0.0));
+        assert!((level.heading - 360.0).abs() < 0.5, "{level:?}");
+    }
+}
@@ -0,0 +1,122 @@
+//! Commands the operator sends back to a vehicle, and the tracking of their acknowledgements
+//!
+//! Commands go out as JSON text over connections that accept them, currently websockets:
+//!
+//! ```json
+//! {"id": 12, "command": "set_heading", "heading": 90.0}
+//! ```
+//!
+//! and the source answers with `{"ack": 12, "ok": true}`, or with `"ok": false` and an
+//! `"error"` explaining why it refused. Commands without an answer time out.
+
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        mpsc,
+    },
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::connection::ConnectionId;
+
+/// How long a source gets to acknowledge a command
+pub const COMMAND_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Unique for the lifetime of the process, so late acknowledgements can not be mixed up
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct CommandId(u64);
+
+impl CommandId {
+    pub fn next() -> Self {
+        static NEXT: AtomicU64 = AtomicU64::new(0);
+
+        Self(NEXT.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub enum Command {
+    Arm,
+    Disarm,
+    SetMode {
+        mode: String,
+    },
+    /// Heading to steer towards, in degrees clockwise from north
+    SetHeading {
+        heading: f32,
+    },
+    /// Take the current orientation as level and north
+    ZeroOrientation,
+}
+
+impl std::fmt::Display for Command {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Command::Arm => f.write_str("Arm"),
+            Command::Disarm => f.write_str("Disarm"),
+            Command::SetMode { mode } => write!(f, "Set mode {mode}"),
+            Command::SetHeading { heading } => write!(f, "Set heading {heading:.0}°"),
+            Command::ZeroOrientation => f.write_str("Zero orientation"),
+        }
+    }
+}
+
+/// A command on its way to a connection, as written to the wire
+#[derive(Debug, Clone, Serialize)]
+pub struct Outbound {
+    pub id: CommandId,
+    #[serde(flatten)]
+    pub command: Command,
+}
+
+/// Sending half of the command queue of a connection, read by its ingest thread
+pub type CommandSender = mpsc::Sender<Outbound>;
+
+/// Answer of a source to a command
+#[derive(Debug, Clone, Deserialize)]
+pub struct Ack {
+    #[serde(rename = "ack")]
+    pub id: CommandId,
+    pub ok: bool,
+    #[serde(default)]
+    pub error: Option<String>,
+}
+
+/// A command sent to a vehicle, kept to show whether it arrived
+#[derive(Debug, Clone)]
+pub struct SentCommand {
+    pub id: CommandId,
+    pub command: Command,
+    pub connection: ConnectionId,
+    pub sent_at: Instant,
+    /// When and how the source answered
+    pub reply: Option<(Instant, Result<(), String>)>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CommandState {
+    Pending,
+    /// Acknowledged after this long
+    Acknowledged(Duration),
+    Rejected(String),
+    TimedOut,
+}
+
+impl SentCommand {
+    pub fn state(&self, now: Instant) -> CommandState {
+        match &self.reply {
+            Some((time, Ok(()))) => {
+                CommandState::Acknowledged(time.saturating_duration_since(self.sent_at))
+            }
+            Some((_, Err(error))) => CommandState::Rejected(error.clone()),
+            None if now.saturating_duration_since(self.sent_at) > COMMAND_TIMEOUT => {
+                CommandState::TimedOut
+            }
+            None => CommandState::Pending,
+        }
+    }
+}
@@ -0,0 +1,226 @@
+//! Configuration of the ingest listeners, as read from the `flock.toml` sections of the same name
+
+use std::{
+    fs, io,
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    path::PathBuf,
+};
+
+use serde::Deserialize;
+
+use crate::telemetry::Position;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    pub enabled: bool,
+    pub bind: IpAddr,
+    pub port: u16,
+    pub tls: Option<TlsConfig>,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            bind: IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            port: 8080,
+            tls: None,
+        }
+    }
+}
+
+impl ServerConfig {
+    pub fn address(&self) -> SocketAddr {
+        SocketAddr::new(self.bind, self.port)
+    }
+
+    /// `ws` or `wss`
+    pub fn scheme(&self) -> &'static str {
+        if self.tls.is_some() {
+            "wss"
+        } else {
+            "ws"
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TlsConfig {
+    /// PEM certificate (chain)
+    pub cert: PathBuf,
+    /// PEM PKCS#8 private key
+    pub key: PathBuf,
+}
+
+impl TlsConfig {
+    pub fn acceptor(&self) -> io::Result<native_tls::TlsAcceptor> {
+        let cert = fs::read(&self.cert)?;
+        let key = fs::read(&self.key)?;
+
+        native_tls::Identity::from_pkcs8(&cert, &key)
+            .and_then(native_tls::TlsAcceptor::new)
+            .map_err(io::Error::other)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MavlinkConfig {
+    pub enabled: bool,
+    pub bind: IpAddr,
+    pub port: u16,
+}
+
+impl Default for MavlinkConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            bind: IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            // The port ground control stations usually listen on
+            port: 14550,
+        }
+    }
+}
+
+impl MavlinkConfig {
+    pub fn address(&self) -> SocketAddr {
+        SocketAddr::new(self.bind, self.port)
+    }
+}
+
+/// JSON telemetry in UDP datagrams, one message per line
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct UdpConfig {
+    pub enabled: bool,
+    pub bind: IpAddr,
+    pub port: u16,
+}
+
+impl Default for UdpConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            bind: IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            port: 8081,
+        }
+    }
+}
+
+impl UdpConfig {
+    pub fn address(&self) -> SocketAddr {
+        SocketAddr::new(self.bind, self.port)
+    }
+}
+
+/// Newline delimited JSON telemetry over TCP
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TcpConfig {
+    pub enabled: bool,
+    pub bind: IpAddr,
+    pub port: u16,
+}
+
+impl Default for TcpConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            bind: IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            port: 8082,
+        }
+    }
+}
+
+impl TcpConfig {
+    pub fn address(&self) -> SocketAddr {
+        SocketAddr::new(self.bind, self.port)
+    }
+}
+
+/// A serial device, reopened whenever it disappears
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SerialConfig {
+    pub enabled: bool,
+    pub device: PathBuf,
+    pub baud: u32,
+    pub framing: Framing,
+}
+
+impl Default for SerialConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            device: PathBuf::from("/dev/ttyUSB0"),
+            // What SiK telemetry radios ship with
+            baud: 57600,
+            framing: Framing::Mavlink,
+        }
+    }
+}
+
+/// How messages are delimited on a byte stream
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+#[serde(rename_all = "snake_case")]
+pub enum Framing {
+    /// MAVLink v1 or v2 frames
+    Mavlink,
+    /// JSON telemetry messages, one per line
+    Json,
+}
+
+/// Simulated vehicles flying manoeuvres, for demos and development without hardware
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SimulatorConfig {
+    pub enabled: bool,
+    /// Number of simulated vehicles
+    pub vehicles: usize,
+    pub scenario: Scenario,
+    /// Telemetry messages per second of every vehicle
+    pub rate: f64,
+    /// Standard deviation of the sensor noise on the attitude, in degrees
+    ///
+    /// Altitude and airspeed get proportionally less of it.
+    pub noise: f32,
+    /// Average number of telemetry dropouts per minute of every vehicle
+    pub dropouts: f64,
+    /// Seed of the random manoeuvres, noise and dropouts [default: random]
+    pub seed: Option<u64>,
+    /// Where the vehicles start flying from
+    pub home: Position,
+}
+
+impl Default for SimulatorConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            vehicles: 1,
+            scenario: Scenario::Scripted,
+            rate: 10.0,
+            noise: 0.5,
+            dropouts: 0.0,
+            seed: None,
+            // Eindhoven University of Technology
+            home: Position {
+                latitude: 51.4484,
+                longitude: 5.4906,
+            },
+        }
+    }
+}
+
+/// What the simulated vehicles fly
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+#[serde(rename_all = "snake_case")]
+pub enum Scenario {
+    /// A fixed sequence of turns, climbs, rolls and loops, repeated
+    Scripted,
+    /// Manoeuvres picked at random
+    Random,
+}
@@ -0,0 +1,131 @@
+//! Bookkeeping of the individual connections feeding a vehicle, for diagnostics
+
+use std::{
+    collections::VecDeque,
+    sync::atomic::{AtomicU64, Ordering},
+    time::{Duration, Instant},
+};
+
+use crate::command::CommandSender;
+
+/// Time span the message rate and jitter are computed over
+const STATS_WINDOW: Duration = Duration::from_secs(5);
+/// Upper bound on the arrival times kept per connection, for very chatty sources
+const MAX_ARRIVALS: usize = 1000;
+
+/// Unique for the lifetime of the process
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ConnectionId(u64);
+
+impl ConnectionId {
+    pub fn next() -> Self {
+        static NEXT: AtomicU64 = AtomicU64::new(0);
+
+        Self(NEXT.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+/// A single source of telemetry for a vehicle, like a websocket or a MAVLink system
+#[derive(Debug, Clone)]
+pub struct Connection {
+    /// Kind of connection, like `websocket` or `mavlink`
+    pub transport: &'static str,
+    /// Where the data comes from, like the address of the peer
+    pub peer: String,
+    pub connected_at: Instant,
+    pub messages: u64,
+    pub bytes: u64,
+    pub parse_errors: u64,
+    pub last_message: Option<Instant>,
+    /// Where to send commands to, if the source accepts them
+    pub commands: Option<CommandSender>,
+    /// Arrival times of recent messages, oldest first
+    arrivals: VecDeque<Instant>,
+}
+
+impl Connection {
+    pub fn new(transport: &'static str, peer: impl Into<String>, connected_at: Instant) -> Self {
+        Self {
+            transport,
+            peer: peer.into(),
+            connected_at,
+            messages: 0,
+            bytes: 0,
+            parse_errors: 0,
+            last_message: None,
+            commands: None,
+            arrivals: VecDeque::new(),
+        }
+    }
+
+    pub fn with_commands(mut self, commands: CommandSender) -> Self {
+        self.commands = Some(commands);
+        self
+    }
+
+    /// Account for a message of `bytes` arriving at `time`
+    pub fn received(&mut self, time: Instant, bytes: usize) {
+        self.messages += 1;
+        self.bytes += bytes as u64;
+        self.last_message = Some(time);
+
+        self.arrivals.push_back(time);
+        while self.arrivals.len() > MAX_ARRIVALS
+            || self
+                .arrivals
+                .front()
+                .is_some_and(|arrival| time.saturating_duration_since(*arrival) > STATS_WINDOW)
+        {
+            self.arrivals.pop_front();
+        }
+    }
+
+    /// Time since the last message, or since connecting if there was none yet
+    pub fn age(&self, now: Instant) -> Duration {
+        now.saturating_duration_since(self.last_message.unwrap_or(self.connected_at))
+    }
+
+    /// Whether nothing arrived within `timeout`
+    pub fn is_stale(&self, now: Instant, timeout: Duration) -> bool {
+        self.age(now) > timeout
+    }
+
+    /// Messages per second over the last few seconds
+    pub fn rate(&self, now: Instant) -> f64 {
+        let window = STATS_WINDOW.min(now.saturating_duration_since(self.connected_at));
+        if window.is_zero() {
+            return 0.0;
+        }
+
+        let recent = self
+            .arrivals
+            .iter()
+            .filter(|arrival| now.saturating_duration_since(**arrival) <= window)
+            .count();
+
+        recent as f64 / window.as_secs_f64()
+    }
+
+    /// Standard deviation of the time between recent messages
+    pub fn jitter(&self) -> Option<Duration> {
+        let intervals = self
+            .arrivals
+            .iter()
+            .zip(self.arrivals.iter().skip(1))
+            .map(|(previous, next)| next.saturating_duration_since(*previous).as_secs_f64())
+            .collect::<Vec<_>>();
+
+        if intervals.len() < 2 {
+            return None;
+        }
+
+        let mean = intervals.iter().sum::<f64>() / intervals.len() as f64;
+        let variance = intervals
+            .iter()
+            .map(|interval| (interval - mean).powi(2))
+            .sum::<f64>()
+            / intervals.len() as f64;
+
+        Some(Duration::from_secs_f64(variance.sqrt()))
+    }
+}
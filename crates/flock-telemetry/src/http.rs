@@ -0,0 +1,264 @@
+//! Just enough HTTP to tell websocket upgrades apart from browsers asking for the phone page
+
+use std::io::{self, Read, Write};
+
+/// The phone side sender, served to any plain `GET`
+const PHONE_PAGE: &str = include_str!("../assets/phone.html");
+
+/// Upper bound on the size of a request head, anything bigger is not a phone
+const MAX_HEAD_LEN: usize = 8 * 1024;
+const MAX_HEADERS: usize = 32;
+
+pub enum Request {
+    /// A websocket handshake, to be completed by tungstenite
+    Upgrade,
+    Get {
+        path: String,
+        host: Option<String>,
+    },
+    Other {
+        method: String,
+    },
+}
+
+/// Read and parse a request head off `stream`
+///
+/// Returns the raw bytes read, so they can be replayed with [`Rewind`].
+pub fn read_request(stream: &mut impl Read) -> io::Result<(Request, Vec<u8>)> {
+    let mut head = Vec::new();
+    let mut buffer = [0; 1024];
+
+    loop {
+        let len = stream.read(&mut buffer)?;
+        if len == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        head.extend_from_slice(&buffer[..len]);
+
+        let mut headers = [httparse::EMPTY_HEADER; MAX_HEADERS];
+        let mut request = httparse::Request::new(&mut headers);
+
+        match request.parse(&head).map_err(io::Error::other)? {
+            httparse::Status::Partial if head.len() < MAX_HEAD_LEN => continue,
+            httparse::Status::Partial => {
+                return Err(io::Error::other("request head too large"));
+            }
+            httparse::Status::Complete(_) => {}
+        }
+
+        let header = |name: &str| {
+            request
+                .headers
+                .iter()
+                .find(|header| header.name.eq_ignore_ascii_case(name))
+                .and_then(|header| std::str::from_utf8(header.value).ok())
+        };
+
+        let request = match request.method {
+            _ if header("upgrade").is_some_and(|value| value.eq_ignore_ascii_case("websocket")) => {
+                Request::Upgrade
+            }
+            Some("GET") => Request::Get {
+                path: request.path.unwrap_or("/").to_owned(),
+                host: header("host").map(str::to_owned),
+            },
+            method => Request::Other {
+                method: method.unwrap_or_default().to_owned(),
+            },
+        };
+
+        return Ok((request, head));
+    }
+}
+
+/// Serve the phone page, pointing its websocket back at the host the browser used to reach us
+pub fn serve_phone_page(
+    stream: &mut impl Write,
+    path: &str,
+    host: Option<&str>,
+    tls: bool,
+) -> io::Result<()> {
+    let route = path.split_once('?').map_or(path, |(route, _)| route);
+    if route != "/" && route != "/index.html" {
+        return respond(stream, "404 Not Found", "text/plain", "Not Found");
+    }
+
+    // The host ends up inside a script, so only allow what a host can actually contain
+    let Some(host) = host.filter(|host| {
+        !host.is_empty()
+            && host
+                .chars()
+                .all(|char| char.is_ascii_alphanumeric() || ".-:[]".contains(char))
+    }) else {
+        return respond(
+            stream,
+            "400 Bad Request",
+            "text/plain",
+            "Missing Host header",
+        );
+    };
+
+    let scheme = if tls { "wss" } else { "ws" };
+    let page = PHONE_PAGE.replace("{{WEBSOCKET_URL}}", &format!("{scheme}://{host}/"));
+
+    respond(stream, "200 OK", "text/html; charset=utf-8", &page)
+}
+
+pub fn respond(
+    stream: &mut impl Write,
+    status: &str,
+    content_type: &str,
+    body: &str,
+) -> io::Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 {status}\r\n\
+         Content-Type: {content_type}\r\n\
+         Content-Length: {}\r\n\
+         Cache-Control: no-store\r\n\
+         Connection: close\r\n\
+         \r\n\
+         {body}",
+        body.len()
+    )?;
+
+    stream.flush()
+}
+
+/// The value of the `name` parameter in a query string like `vehicle=drone%201&mount=flat`
+///
+/// Keys and values are decoded like an HTML form encodes them, with `+` for a space.
+pub fn query_parameter(query: &str, name: &str) -> Option<String> {
+    query
+        .split('&')
+        .map(|pair| pair.split_once('=').unwrap_or((pair, "")))
+        .find(|(key, _)| percent_decode(key) == name)
+        .map(|(_, value)| percent_decode(value))
+}
+
+/// Undo percent-encoding, leaving malformed escapes as they are
+fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+
+    while index < bytes.len() {
+        let escaped = bytes
+            .get(index + 1..index + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+
+        match (bytes[index], escaped) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                index += 3;
+                continue;
+            }
+            (b'+', _) => decoded.push(b' '),
+            (byte, _) => decoded.push(byte),
+        }
+        index += 1;
+    }
+
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// A stream that first replays bytes that were already read from it
+pub struct Rewind<S> {
+    prefix: Vec<u8>,
+    position: usize,
+    inner: S,
+}
+
+impl<S> Rewind<S> {
+    pub fn new(prefix: Vec<u8>, inner: S) -> Self {
+        Self {
+            prefix,
+            position: 0,
+            inner,
+        }
+    }
+}
+
+impl<S: Read> Read for Rewind<S> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let remaining = &self.prefix[self.position..];
+
+        if remaining.is_empty() {
+            return self.inner.read(buf);
+        }
+
+        let len = remaining.len().min(buf.len());
+        buf[..len].copy_from_slice(&remaining[..len]);
+        self.position += len;
+
+        Ok(len)
+    }
+}
+
+impl<S: Write> Write for Rewind<S> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.inner.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn query() {
+        let query = "mount=flat&vehicle=drone%201&name=caf%C3%A9+noir&empty&bad=100%+%zz";
+
+        assert_eq!(
+            query_parameter(query, "vehicle").as_deref(),
+            Some("drone 1")
+        );
+        assert_eq!(query_parameter(query, "mount").as_deref(), Some("flat"));
+        assert_eq!(query_parameter(query, "name").as_deref(), Some("café noir"));
+        assert_eq!(query_parameter(query, "empty").as_deref(), Some(""));
+        assert_eq!(query_parameter(query, "bad").as_deref(), Some("100% %zz"));
+        assert_eq!(query_parameter(query, "missing"), None);
+        assert_eq!(query_parameter("a%26b=1", "a&b").as_deref(), Some("1"));
+    }
+
+    #[test]
+    fn request_head() {
+        let mut stream: &[u8] = b"GET /?vehicle=phone HTTP/1.1\r\nHost: 10.0.0.2:8080\r\n\r\n";
+        let (request, head) = read_request(&mut stream).unwrap();
+
+        assert!(matches!(
+            request,
+            Request::Get { path, host: Some(host) } if path == "/?vehicle=phone" && host == "10.0.0.2:8080"
+        ));
+        assert!(head.ends_with(b"\r\n\r\n"));
+
+        let mut stream: &[u8] = b"GET / HTTP/1.1\r\nUpgrade: WebSocket\r\n\r\n";
+        assert!(matches!(
+            read_request(&mut stream).unwrap().0,
+            Request::Upgrade
+        ));
+    }
+
+    #[test]
+    fn phone_page() {
+        let page = |path: &str, host: Option<&str>| {
+            let mut response = Vec::new();
+            serve_phone_page(&mut response, path, host, true).unwrap();
+
+            String::from_utf8(response).unwrap()
+        };
+
+        let response = page("/?vehicle=phone", Some("10.0.0.2:8080"));
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        assert!(response.contains("wss://10.0.0.2:8080/"));
+
+        assert!(page("/favicon.ico", Some("10.0.0.2")).starts_with("HTTP/1.1 404"));
+        assert!(page("/", Some("evil\"+alert(1)+\"")).starts_with("HTTP/1.1 400"));
+        assert!(page("/", None).starts_with("HTTP/1.1 400"));
+    }
+}
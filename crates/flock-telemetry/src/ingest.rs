@@ -0,0 +1,262 @@
+//! Transports feeding telemetry into Flock
+//!
+//! Every transport only deals with framing and connection lifetimes, and hands the
+//! messages it receives to an [`Ingest`] handle, which decodes, records and forwards them to
+//! the vehicle registry the same way for all of them.
+
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    hash::Hash,
+    io,
+    sync::{mpsc, Arc},
+    thread,
+    time::{Duration, Instant},
+};
+
+use serde::Deserialize;
+use tracing::{debug, error, trace};
+
+use crate::{
+    command::{Ack, Outbound},
+    connection::ConnectionId,
+    recording::Recorder,
+    status::{ListenerStatus, Status},
+    telemetry::Telemetry,
+    vehicle::{VehicleId, VehicleUpdates},
+};
+
+pub mod mavlink;
+pub mod serial;
+pub mod simulator;
+pub mod tcp;
+pub mod udp;
+pub mod websocket;
+
+/// How long to wait before trying to bind a listener again
+const BIND_RETRY: Duration = Duration::from_secs(5);
+
+/// Longest line of a stream transport, anything longer is line noise or a misbehaving client
+const MAX_LINE_LEN: usize = 64 * 1024;
+
+/// Shared handle to everything a transport delivers telemetry to, cloned into every thread
+#[derive(Clone)]
+pub struct Ingest {
+    updates: VehicleUpdates,
+    recorder: Recorder,
+    status: Status,
+    request_repaint: Arc<dyn Fn() + Send + Sync>,
+}
+
+impl Ingest {
+    pub fn new(
+        updates: VehicleUpdates,
+        recorder: Recorder,
+        status: Status,
+        request_repaint: impl Fn() + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            updates,
+            recorder,
+            status,
+            request_repaint: Arc::new(request_repaint),
+        }
+    }
+
+    pub fn request_repaint(&self) {
+        (self.request_repaint)();
+    }
+
+    pub fn status(&self) -> &Status {
+        &self.status
+    }
+
+    /// Update the status of the listener called `name` in the status bar
+    pub fn listener(&self, name: &'static str, update: impl FnOnce(&mut ListenerStatus)) {
+        update(self.status.lock().listeners.entry(name).or_default());
+        self.request_repaint();
+    }
+
+    pub fn connected(
+        &self,
+        id: &VehicleId,
+        transport: &'static str,
+        peer: impl Into<String>,
+    ) -> ConnectionId {
+        let connection = self.updates.connected(id, transport, peer);
+        self.request_repaint();
+
+        connection
+    }
+
+    /// A connection that accepts commands, see [`VehicleUpdates::connected_with_commands`]
+    pub fn connected_with_commands(
+        &self,
+        id: &VehicleId,
+        transport: &'static str,
+        peer: impl Into<String>,
+    ) -> (ConnectionId, mpsc::Receiver<Outbound>) {
+        let connection = self.updates.connected_with_commands(id, transport, peer);
+        self.request_repaint();
+
+        connection
+    }
+
+    pub fn disconnected(&self, id: &VehicleId, connection: ConnectionId) {
+        self.updates.disconnected(id, connection);
+        self.request_repaint();
+    }
+
+    /// A decoded telemetry update that took `bytes` on the wire
+    pub fn telemetry(
+        &self,
+        id: &VehicleId,
+        connection: ConnectionId,
+        telemetry: Telemetry,
+        bytes: usize,
+    ) {
+        self.recorder.record(id, &telemetry);
+        self.updates.telemetry(id, connection, telemetry, bytes);
+        self.request_repaint();
+    }
+
+    /// A JSON telemetry message, see [`Telemetry::from_json`], or the answer to a command
+    pub fn json(&self, id: &VehicleId, connection: ConnectionId, text: &str) {
+        if let Ok(ack) = serde_json::from_str::<Ack>(text) {
+            self.updates.acknowledged(id, ack);
+            self.request_repaint();
+            return;
+        }
+
+        match Telemetry::from_json(text) {
+            Ok(telemetry) => self.telemetry(id, connection, telemetry, text.len()),
+            Err(error) => {
+                debug!(%id, %error, "invalid telemetry message");
+                self.parse_failed(id, connection, error, text);
+            }
+        }
+    }
+
+    /// A binary telemetry message, see [`Telemetry::from_binary`]
+    pub fn binary(&self, id: &VehicleId, connection: ConnectionId, bytes: &[u8]) {
+        match Telemetry::from_binary(bytes) {
+            Ok(telemetry) => self.telemetry(id, connection, telemetry, bytes.len()),
+            Err(error) => {
+                debug!(%id, %error, "invalid binary telemetry message");
+                self.parse_failed(id, connection, error, hex_dump(bytes));
+            }
+        }
+    }
+
+    /// A message the transport could not even hand to [`Self::json`] or [`Self::binary`]
+    pub fn parse_failed(
+        &self,
+        id: &VehicleId,
+        connection: ConnectionId,
+        error: impl Display,
+        payload: impl Into<String>,
+    ) {
+        self.updates.parse_failed(id, connection, error, payload);
+        self.request_repaint();
+    }
+}
+
+/// Printable form of a binary message that failed to parse, shown in the vehicle details
+fn hex_dump(bytes: &[u8]) -> String {
+    const MAX_LEN: usize = 64;
+
+    let mut dump = bytes
+        .iter()
+        .take(MAX_LEN)
+        .map(|byte| format!("{byte:02x}"))
+        .collect::<Vec<_>>()
+        .join(" ");
+    if bytes.len() > MAX_LEN {
+        dump.push_str(&format!(" .. ({} bytes)", bytes.len()));
+    }
+
+    dump
+}
+
+/// Vehicle a JSON message on a transport without a handshake names in its optional `vehicle` field
+fn addressed_vehicle(text: &str) -> Option<VehicleId> {
+    #[derive(Deserialize)]
+    struct Addressed {
+        vehicle: Option<VehicleId>,
+    }
+
+    serde_json::from_str::<Addressed>(text)
+        .ok()
+        .and_then(|addressed| addressed.vehicle)
+        .filter(|id| !id.as_str().trim().is_empty())
+}
+
+/// Connections over connectionless transports, considered closed once nothing arrived for a while
+struct Sessions<K> {
+    timeout: Duration,
+    last_heard: HashMap<K, (Instant, ConnectionId)>,
+}
+
+impl<K: Hash + Eq> Sessions<K> {
+    fn new(timeout: Duration) -> Self {
+        Self {
+            timeout,
+            last_heard: HashMap::new(),
+        }
+    }
+
+    /// Note that `key` was heard from just now, calling `connect` if it is new
+    fn heard(&mut self, key: K, connect: impl FnOnce() -> ConnectionId) -> ConnectionId {
+        let (last_heard, connection) = self
+            .last_heard
+            .entry(key)
+            .or_insert_with(|| (Instant::now(), connect()));
+        *last_heard = Instant::now();
+
+        *connection
+    }
+
+    /// Forget every session that timed out, calling `disconnect` for each
+    fn expire(&mut self, mut disconnect: impl FnMut(&K, ConnectionId)) {
+        self.last_heard.retain(|key, (last_heard, connection)| {
+            if last_heard.elapsed() < self.timeout {
+                return true;
+            }
+
+            disconnect(key, *connection);
+
+            false
+        });
+    }
+
+    /// Forget every session, calling `disconnect` for each
+    fn close(self, mut disconnect: impl FnMut(&K, ConnectionId)) {
+        for (key, (_, connection)) in self.last_heard {
+            disconnect(&key, connection);
+        }
+    }
+}
+
+/// Bind a listener, retrying until it succeeds, and report it as `url` in the status bar
+fn bind_retrying<T>(
+    ingest: &Ingest,
+    name: &'static str,
+    url: &str,
+    mut bind: impl FnMut() -> io::Result<T>,
+) -> T {
+    loop {
+        match bind() {
+            Ok(listener) => {
+                trace!(listener = name, url, "listening");
+                ingest.listener(name, |status| status.bound(url));
+
+                return listener;
+            }
+            Err(error) => {
+                error!(%error, listener = name, url, "failed to bind, retrying");
+                ingest.listener(name, |status| status.bind_failed(&error));
+                thread::sleep(BIND_RETRY);
+            }
+        }
+    }
+}
@@ -0,0 +1,160 @@
+//! MAVLink over UDP, as sent by autopilots and telemetry radios
+
+use std::{io, net::UdpSocket, time::Duration};
+
+use tracing::{debug, trace, warn};
+
+use super::{bind_retrying, Ingest, Sessions};
+use crate::{
+    config::MavlinkConfig,
+    mavlink::{Decoder, Message},
+    vehicle::VehicleId,
+};
+
+/// A vehicle is considered gone after missing this many 1Hz heartbeats
+const MAVLINK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// `MAV_TYPE_GCS`, ground stations talk on the same link but are not vehicles
+const MAV_TYPE_GCS: u8 = 6;
+
+pub fn mavlink_thread(config: MavlinkConfig, ingest: Ingest) {
+    let address = config.address();
+
+    let socket = bind_retrying(&ingest, "MAVLink", &format!("udp://{address}"), || {
+        let socket = UdpSocket::bind(address)?;
+        socket.set_read_timeout(Some(Duration::from_secs(1)))?;
+
+        Ok(socket)
+    });
+
+    let mut stream = MavlinkStream::new("mavlink");
+    let mut buffer = [0; 2048];
+
+    loop {
+        match socket.recv_from(&mut buffer) {
+            Ok((len, peer)) => {
+                trace!(len, %peer, "mavlink datagram");
+                // Frames never span datagrams, and the next one may well be from another sender
+                stream.reset();
+                stream.received(&buffer[..len], &peer.to_string(), &ingest);
+            }
+            Err(error)
+                if matches!(
+                    error.kind(),
+                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                ) => {}
+            Err(error) => {
+                warn!(%error, "error receiving mavlink datagram");
+            }
+        }
+
+        stream.expire(&ingest);
+    }
+}
+
+/// The vehicles talking on a single MAVLink link, each system id becoming a connection
+pub(super) struct MavlinkStream {
+    transport: &'static str,
+    decoder: Decoder,
+    sessions: Sessions<u8>,
+}
+
+impl MavlinkStream {
+    pub fn new(transport: &'static str) -> Self {
+        Self {
+            transport,
+            decoder: Decoder::default(),
+            sessions: Sessions::new(MAVLINK_TIMEOUT),
+        }
+    }
+
+    /// Decode the frames completed by `bytes`, which came from `peer`
+    pub fn received(&mut self, bytes: &[u8], peer: &str, ingest: &Ingest) {
+        self.decoder.push(bytes);
+
+        while let Some(frame) = self.decoder.next_frame() {
+            let Some(message) = Message::decode(&frame) else {
+                continue;
+            };
+
+            if let Message::Heartbeat(heartbeat) = message {
+                if heartbeat.mav_type == MAV_TYPE_GCS {
+                    continue;
+                }
+            }
+
+            let id = mavlink_vehicle_id(frame.system_id);
+            let connection = self.sessions.heard(frame.system_id, || {
+                debug!(%id, "mavlink vehicle appeared");
+
+                ingest.connected(&id, self.transport, peer)
+            });
+
+            ingest.telemetry(&id, connection, message.telemetry(), frame.len);
+        }
+    }
+
+    /// Forget any partial frame, keeping the vehicles connected
+    pub fn reset(&mut self) {
+        self.decoder = Decoder::default();
+    }
+
+    /// Disconnect the vehicles that went quiet
+    pub fn expire(&mut self, ingest: &Ingest) {
+        self.sessions.expire(|system_id, connection| {
+            let id = mavlink_vehicle_id(*system_id);
+            debug!(%id, "mavlink vehicle timed out");
+            ingest.disconnected(&id, connection);
+        });
+    }
+
+    /// Disconnect every vehicle, when the link itself is gone
+    pub fn close(self, ingest: &Ingest) {
+        self.sessions.close(|system_id, connection| {
+            ingest.disconnected(&mavlink_vehicle_id(*system_id), connection);
+        });
+    }
+}
+
+fn mavlink_vehicle_id(system_id: u8) -> VehicleId {
+    VehicleId::new(format!("MAV {system_id}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        calibration::Calibrations, recording::Recorder, status::Status, vehicle::Vehicles,
+    };
+
+    #[test]
+    fn interleaved_senders() {
+        // HEARTBEAT of an armed quadrotor with system id 7
+        #[rustfmt::skip]
+        let heartbeat = [
+            0xFE, 0x09, 0x00, 0x07, 0x01, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x02, 0x03, 0x80, 0x04, 0x03,
+            0x50, 0x24,
+        ];
+        let (mut vehicles, updates) = Vehicles::new(Calibrations::default());
+        let ingest = Ingest::new(updates, Recorder::default(), Status::default(), || {});
+        let mut stream = MavlinkStream::new("mavlink");
+
+        // The start of a SYS_STATUS, which is skipped whole, from a sender cut off mid-frame
+        stream.reset();
+        stream.received(
+            &[0xFE, 0x1F, 0x00, 0x01, 0x01, 0x01],
+            "10.0.0.1:14550",
+            &ingest,
+        );
+        stream.reset();
+        stream.received(&heartbeat, "10.0.0.2:14550", &ingest);
+        stream.close(&ingest);
+        vehicles.drain();
+
+        assert_eq!(
+            vehicles[&VehicleId::new("MAV 7")].telemetry.armed,
+            Some(true)
+        );
+    }
+}
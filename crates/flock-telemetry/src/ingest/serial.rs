@@ -0,0 +1,272 @@
+//! Telemetry from a serial device, like a telemetry radio or a flight controller on USB
+//!
+//! The device is reopened whenever it disappears, so radios can be unplugged and replugged.
+
+use std::{
+    collections::HashMap,
+    io::{self, Read},
+    thread,
+    time::Duration,
+};
+
+use tracing::{debug, trace, warn};
+
+use super::{addressed_vehicle, mavlink::MavlinkStream, Ingest, MAX_LINE_LEN};
+use crate::{
+    config::{Framing, SerialConfig},
+    connection::ConnectionId,
+    vehicle::VehicleId,
+};
+
+const LISTENER: &str = "Serial";
+
+/// How long to wait before trying to open the device again
+const REOPEN_DELAY: Duration = Duration::from_secs(2);
+
+pub fn serial_thread(config: SerialConfig, ingest: Ingest) {
+    let device = config.device.to_string_lossy().into_owned();
+
+    loop {
+        let port = serialport::new(&device, config.baud)
+            .timeout(Duration::from_secs(1))
+            .open();
+
+        match port {
+            Ok(mut port) => {
+                debug!(device, baud = config.baud, "opened serial device");
+                ingest.listener(LISTENER, |status| {
+                    status.bound(format!("{device} at {} baud", config.baud))
+                });
+
+                let error = session(&mut port, &device, config.framing, &ingest);
+                warn!(%error, device, "lost serial device, reopening");
+                ingest.listener(LISTENER, |status| status.bind_failed(&error));
+            }
+            Err(error) => {
+                debug!(%error, device, "failed to open serial device, retrying");
+                ingest.listener(LISTENER, |status| status.bind_failed(&error));
+            }
+        }
+
+        thread::sleep(REOPEN_DELAY);
+    }
+}
+
+/// Read telemetry from the open `device` until it fails, returning why
+fn session(port: &mut impl Read, device: &str, framing: Framing, ingest: &Ingest) -> io::Error {
+    let mut stream = match framing {
+        Framing::Mavlink => Stream::Mavlink(MavlinkStream::new("serial")),
+        Framing::Json => Stream::Json(JsonLines::default()),
+    };
+    let mut buffer = [0; 4096];
+
+    let error = loop {
+        match port.read(&mut buffer) {
+            // A terminal reads nothing once it hung up
+            Ok(0) => break io::ErrorKind::UnexpectedEof.into(),
+            Ok(len) => {
+                trace!(len, device, "serial data");
+                stream.received(&buffer[..len], device, ingest);
+            }
+            Err(error)
+                if matches!(
+                    error.kind(),
+                    io::ErrorKind::TimedOut
+                        | io::ErrorKind::WouldBlock
+                        | io::ErrorKind::Interrupted
+                ) => {}
+            Err(error) => break error,
+        }
+
+        stream.expire(ingest);
+    };
+
+    stream.close(ingest);
+
+    error
+}
+
+enum Stream {
+    Mavlink(MavlinkStream),
+    Json(JsonLines),
+}
+
+impl Stream {
+    fn received(&mut self, bytes: &[u8], device: &str, ingest: &Ingest) {
+        match self {
+            Stream::Mavlink(stream) => stream.received(bytes, device, ingest),
+            Stream::Json(stream) => stream.received(bytes, device, ingest),
+        }
+    }
+
+    fn expire(&mut self, ingest: &Ingest) {
+        match self {
+            Stream::Mavlink(stream) => stream.expire(ingest),
+            // Vehicles on a JSON stream stay connected as long as the device
+            Stream::Json(_) => {}
+        }
+    }
+
+    fn close(self, ingest: &Ingest) {
+        match self {
+            Stream::Mavlink(stream) => stream.close(ingest),
+            Stream::Json(stream) => {
+                for (id, connection) in stream.connections {
+                    ingest.disconnected(&id, connection);
+                }
+            }
+        }
+    }
+}
+
+/// Newline delimited JSON, naming its vehicle in the `vehicle` field or else belonging to the device
+#[derive(Default)]
+struct JsonLines {
+    line: Vec<u8>,
+    /// Whether the current line outgrew [`MAX_LINE_LEN`], and is skipped up to its end
+    overlong: bool,
+    connections: HashMap<VehicleId, ConnectionId>,
+}
+
+impl JsonLines {
+    fn received(&mut self, bytes: &[u8], device: &str, ingest: &Ingest) {
+        for &byte in bytes {
+            if byte != b'\n' {
+                if self.line.len() < MAX_LINE_LEN {
+                    self.line.push(byte);
+                } else {
+                    self.overlong = true;
+                }
+                continue;
+            }
+
+            if std::mem::take(&mut self.overlong) {
+                let prefix = String::from_utf8_lossy(&self.line[..64]).into_owned();
+                self.line.clear();
+
+                debug!(device, "line longer than {MAX_LINE_LEN} bytes, skipped");
+                let id = VehicleId::new(device);
+                let connection = self.connection(&id, device, ingest);
+                ingest.parse_failed(
+                    &id,
+                    connection,
+                    format!("line longer than {MAX_LINE_LEN} bytes"),
+                    format!("{prefix} .."),
+                );
+                continue;
+            }
+
+            let line = String::from_utf8_lossy(&self.line).into_owned();
+            self.line.clear();
+
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let id = addressed_vehicle(line).unwrap_or_else(|| VehicleId::new(device));
+            let connection = self.connection(&id, device, ingest);
+
+            ingest.json(&id, connection, line);
+        }
+    }
+
+    fn connection(&mut self, id: &VehicleId, device: &str, ingest: &Ingest) -> ConnectionId {
+        *self
+            .connections
+            .entry(id.clone())
+            .or_insert_with(|| ingest.connected(id, "serial", device))
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::{io::Write, time::Instant};
+
+    use serialport::TTYPort;
+
+    use super::*;
+    use crate::{
+        calibration::Calibrations, recording::Recorder, status::Status, vehicle::Vehicles,
+    };
+
+    /// Feeds `data` through a pseudo-terminal and hangs up once `read` holds for the vehicles, like
+    /// a radio being unplugged
+    fn run_pty(
+        framing: Framing,
+        data: &[u8],
+        read: impl Fn(&Vehicles) -> bool,
+    ) -> (Vehicles, io::Error) {
+        let (mut radio, mut device) = TTYPort::pair().expect("failed to open a pseudo-terminal");
+        let (mut vehicles, updates) = Vehicles::new(Calibrations::default());
+        let ingest = Ingest::new(updates, Recorder::default(), Status::default(), || {});
+
+        let reader = thread::spawn(move || session(&mut device, "pty", framing, &ingest));
+
+        radio.write_all(data).unwrap();
+        radio.flush().unwrap();
+        // Closing the other end before the data is read would discard it
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while !read(&vehicles) && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(10));
+            vehicles.drain();
+        }
+        drop(radio);
+
+        (vehicles, reader.join().unwrap())
+    }
+
+    #[test]
+    fn json_lines() {
+        let mut data =
+            b"{\"heading\": 90.0}\r\n\n{\"vehicle\": \"radio\", \"pitch\": 5.0}\nnot json\n"
+                .to_vec();
+        // An overlong line is skipped whole, not parsed up to where it was cut off
+        data.extend_from_slice(b"{\"pitch\": 1.0}");
+        data.resize(data.len() + MAX_LINE_LEN, b' ');
+        data.extend_from_slice(b"garbage\n{\"roll\": 2.0}\n{\"roll\":");
+
+        let (mut vehicles, _) = run_pty(Framing::Json, &data, |vehicles| {
+            vehicles
+                .get(&VehicleId::new("pty"))
+                .is_some_and(|device| device.telemetry.roll.is_some())
+        });
+        vehicles.drain();
+
+        let device = &vehicles[&VehicleId::new("pty")];
+        assert_eq!(device.telemetry.heading, Some(90.0));
+        assert_eq!(device.telemetry.pitch, None);
+        assert_eq!(device.telemetry.roll, Some(2.0));
+        assert_eq!(device.parse_errors, 2);
+        assert!(device.connections.is_empty());
+
+        let radio = &vehicles[&VehicleId::new("radio")];
+        assert_eq!(radio.telemetry.pitch, Some(5.0));
+        assert_eq!(radio.telemetry.heading, None);
+        assert!(radio.connections.is_empty());
+    }
+
+    #[test]
+    fn mavlink() {
+        // HEARTBEAT of an armed quadrotor with system id 7, followed by half a frame
+        #[rustfmt::skip]
+        let heartbeat = [
+            0xFE, 0x09, 0x00, 0x07, 0x01, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x02, 0x03, 0x80, 0x04, 0x03,
+            0x50, 0x24,
+            0xFE, 0x09, 0x01,
+        ];
+
+        let (mut vehicles, _) = run_pty(Framing::Mavlink, &heartbeat, |vehicles| {
+            vehicles
+                .get(&VehicleId::new("MAV 7"))
+                .is_some_and(|vehicle| vehicle.telemetry.armed.is_some())
+        });
+        vehicles.drain();
+
+        let vehicle = &vehicles[&VehicleId::new("MAV 7")];
+        assert_eq!(vehicle.telemetry.armed, Some(true));
+        assert!(vehicle.connections.is_empty());
+        assert_eq!(vehicles.len(), 1);
+    }
+}
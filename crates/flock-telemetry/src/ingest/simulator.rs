@@ -0,0 +1,582 @@
+//! Simulated vehicles, for demos and for working on Flock without any hardware
+//!
+//! Every vehicle flies a simple point mass model through a sequence of manoeuvres, either the
+//! fixed [`SCRIPT`] or picked at random, and its telemetry goes through [`Ingest`] like that of
+//! a real vehicle, with optional sensor noise and dropouts on top.
+
+use std::{
+    f64::consts::PI,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use tracing::debug;
+
+use super::Ingest;
+use crate::{
+    config::{Scenario, SimulatorConfig},
+    connection::ConnectionId,
+    telemetry::{Battery, Gps, GpsFix, Position, Telemetry},
+    vehicle::VehicleId,
+};
+
+/// m/s²
+const GRAVITY: f32 = 9.81;
+/// m/s, with the wings level
+const CRUISE_AIRSPEED: f32 = 18.0;
+/// m above mean sea level of the home position
+const HOME_ALTITUDE: f32 = 20.0;
+/// m above the home position the vehicles start at
+const START_ALTITUDE: f32 = 100.0;
+/// Random climbs and descents stay between these heights above home, in m
+const MIN_ALTITUDE: f32 = 40.0;
+const MAX_ALTITUDE: f32 = 200.0;
+/// How fast the vehicles roll and pitch towards the attitude a manoeuvre asks for, in °/s
+const ROLL_RATE: f32 = 60.0;
+const PITCH_RATE: f32 = 15.0;
+/// m per degree of latitude
+const METERS_PER_DEGREE: f64 = 111_320.0;
+
+/// The manoeuvres of [`Scenario::Scripted`], flown in order and repeated
+const SCRIPT: &[Manoeuvre] = &[
+    Manoeuvre::Cruise { duration: 5.0 },
+    Manoeuvre::Turn {
+        bank: 30.0,
+        duration: 12.0,
+    },
+    Manoeuvre::Cruise { duration: 3.0 },
+    Manoeuvre::Climb {
+        pitch: 10.0,
+        duration: 8.0,
+    },
+    Manoeuvre::Cruise { duration: 3.0 },
+    Manoeuvre::Roll {
+        direction: 1.0,
+        duration: 4.0,
+    },
+    Manoeuvre::Cruise { duration: 3.0 },
+    Manoeuvre::Turn {
+        bank: -45.0,
+        duration: 8.0,
+    },
+    Manoeuvre::Cruise { duration: 3.0 },
+    Manoeuvre::Loop { duration: 8.0 },
+    Manoeuvre::Cruise { duration: 3.0 },
+    Manoeuvre::Climb {
+        pitch: -10.0,
+        duration: 8.0,
+    },
+    Manoeuvre::Roll {
+        direction: -1.0,
+        duration: 4.0,
+    },
+    Manoeuvre::Turn {
+        bank: 60.0,
+        duration: 6.0,
+    },
+];
+
+/// Handle to running simulated vehicles, which disconnect when it is stopped or dropped
+pub struct Simulator {
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Simulator {
+    /// Start flying the vehicles of `config`, feeding their telemetry to `ingest`
+    pub fn start(config: SimulatorConfig, ingest: Ingest) -> Self {
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread = thread::spawn({
+            let stop = stop.clone();
+
+            move || simulator_thread(config, ingest, &stop)
+        });
+
+        Self {
+            stop,
+            thread: Some(thread),
+        }
+    }
+}
+
+impl Drop for Simulator {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn simulator_thread(config: SimulatorConfig, ingest: Ingest, stop: &AtomicBool) {
+    let period = Duration::from_secs_f64(1.0 / config.rate.clamp(0.1, 1000.0));
+    let mut rng = Rng::new(config.seed.unwrap_or_else(|| {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos() as u64
+    }));
+
+    let mut vehicles = (0..config.vehicles)
+        .map(|index| {
+            let vehicle = SimulatedVehicle::new(&config, index, rng.next_u64());
+
+            (vehicle, None::<ConnectionId>)
+        })
+        .collect::<Vec<_>>();
+    debug!(vehicles = vehicles.len(), scenario = ?config.scenario, "simulator started");
+
+    let peer = match config.scenario {
+        Scenario::Scripted => "scripted",
+        Scenario::Random => "random",
+    };
+    let mut last_step = Instant::now();
+
+    while !stop.load(Ordering::Relaxed) {
+        thread::sleep(period.saturating_sub(last_step.elapsed()));
+        let now = Instant::now();
+        let dt = (now - last_step).as_secs_f64();
+        last_step = now;
+
+        for (vehicle, connection) in &mut vehicles {
+            let Some(telemetry) = vehicle.step(dt) else {
+                continue;
+            };
+            let connection =
+                *connection.get_or_insert_with(|| ingest.connected(&vehicle.id, "simulator", peer));
+            // What the message would have taken as JSON, for the connection statistics
+            let bytes = serde_json::to_vec(&telemetry).map_or(0, |json| json.len());
+
+            ingest.telemetry(&vehicle.id, connection, telemetry, bytes);
+        }
+    }
+
+    for (vehicle, connection) in vehicles {
+        if let Some(connection) = connection {
+            ingest.disconnected(&vehicle.id, connection);
+        }
+    }
+    debug!("simulator stopped");
+}
+
+/// Something a simulated vehicle flies for a while
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Manoeuvre {
+    /// Straight and level
+    Cruise { duration: f64 },
+    /// A coordinated turn at a constant bank angle in degrees, negative to the left
+    Turn { bank: f32, duration: f64 },
+    /// Wings level at a constant pitch in degrees, negative to descend
+    Climb { pitch: f32, duration: f64 },
+    /// A full aileron roll, `direction` 1 to the right and -1 to the left
+    Roll { direction: f32, duration: f64 },
+    /// A full inside loop
+    Loop { duration: f64 },
+}
+
+impl Manoeuvre {
+    fn duration(self) -> f64 {
+        match self {
+            Manoeuvre::Cruise { duration }
+            | Manoeuvre::Turn { duration, .. }
+            | Manoeuvre::Climb { duration, .. }
+            | Manoeuvre::Roll { duration, .. }
+            | Manoeuvre::Loop { duration } => duration,
+        }
+    }
+
+    /// Reported as the flight mode, to see what the vehicle is up to
+    fn name(self) -> &'static str {
+        match self {
+            Manoeuvre::Cruise { .. } => "cruise",
+            Manoeuvre::Turn { .. } => "turn",
+            Manoeuvre::Climb { pitch, .. } if pitch < 0.0 => "descend",
+            Manoeuvre::Climb { .. } => "climb",
+            Manoeuvre::Roll { .. } => "roll",
+            Manoeuvre::Loop { .. } => "loop",
+        }
+    }
+
+    /// A random manoeuvre, climbing or descending back when close to the altitude limits
+    fn random(rng: &mut Rng, relative_altitude: f32) -> Self {
+        let either_way = |rng: &mut Rng| if rng.uniform() < 0.5 { -1.0 } else { 1.0 };
+
+        match rng.below(5) {
+            0 => Manoeuvre::Cruise {
+                duration: rng.range(2.0, 6.0),
+            },
+            1 => Manoeuvre::Turn {
+                bank: either_way(rng) * rng.range(15.0, 60.0) as f32,
+                duration: rng.range(5.0, 15.0),
+            },
+            2 => {
+                let direction = if relative_altitude < MIN_ALTITUDE {
+                    1.0
+                } else if relative_altitude > MAX_ALTITUDE {
+                    -1.0
+                } else {
+                    either_way(rng)
+                };
+
+                Manoeuvre::Climb {
+                    pitch: direction * rng.range(5.0, 20.0) as f32,
+                    duration: rng.range(4.0, 10.0),
+                }
+            }
+            3 => Manoeuvre::Roll {
+                direction: either_way(rng),
+                duration: rng.range(3.0, 5.0),
+            },
+            _ => Manoeuvre::Loop {
+                duration: rng.range(6.0, 10.0),
+            },
+        }
+    }
+}
+
+/// Where a simulated vehicle is and where it is going, without any noise
+#[derive(Debug, Clone, Copy)]
+struct FlightState {
+    /// degrees, 0-360
+    heading: f32,
+    /// degrees, -90-90
+    pitch: f32,
+    /// degrees, -180-180
+    roll: f32,
+    /// m above the home position
+    altitude: f32,
+    /// m/s
+    airspeed: f32,
+    /// m/s
+    vertical_speed: f32,
+    position: Position,
+    /// percent
+    battery: f32,
+}
+
+struct SimulatedVehicle {
+    id: VehicleId,
+    rng: Rng,
+    scenario: Scenario,
+    noise: f32,
+    dropouts: f64,
+    /// Index in the script of the manoeuvre after the current one
+    next: usize,
+    manoeuvre: Manoeuvre,
+    /// Seconds into the current manoeuvre
+    elapsed: f64,
+    /// The state when the current manoeuvre started, which rolls and loops are flown relative to
+    entry: FlightState,
+    /// Seconds left of the current dropout
+    dropout: f64,
+    state: FlightState,
+}
+
+impl SimulatedVehicle {
+    /// The `index`th vehicle of `config`, spread out around home so they do not fly in lockstep
+    fn new(config: &SimulatorConfig, index: usize, seed: u64) -> Self {
+        let spread = (index as f64 * 50.0) / METERS_PER_DEGREE;
+        let state = FlightState {
+            heading: (index as f32 * 360.0 / config.vehicles.max(1) as f32).rem_euclid(360.0),
+            pitch: 0.0,
+            roll: 0.0,
+            altitude: START_ALTITUDE,
+            airspeed: CRUISE_AIRSPEED,
+            vertical_speed: 0.0,
+            position: Position {
+                latitude: config.home.latitude + spread,
+                longitude: config.home.longitude,
+            },
+            battery: 100.0,
+        };
+
+        let mut vehicle = Self {
+            id: VehicleId::new(format!("sim-{}", index + 1)),
+            rng: Rng::new(seed),
+            scenario: config.scenario,
+            noise: config.noise,
+            dropouts: config.dropouts,
+            next: index * 3,
+            manoeuvre: Manoeuvre::Cruise { duration: 0.0 },
+            elapsed: 0.0,
+            entry: state,
+            dropout: 0.0,
+            state,
+        };
+        vehicle.next_manoeuvre();
+
+        vehicle
+    }
+
+    fn next_manoeuvre(&mut self) {
+        self.manoeuvre = match self.scenario {
+            Scenario::Scripted => {
+                let manoeuvre = SCRIPT[self.next % SCRIPT.len()];
+                self.next = (self.next + 1) % SCRIPT.len();
+
+                manoeuvre
+            }
+            Scenario::Random => Manoeuvre::random(&mut self.rng, self.state.altitude),
+        };
+        self.elapsed = 0.0;
+        self.entry = self.state;
+    }
+
+    /// Fly for `dt` seconds, and report the telemetry unless the link is dropping out
+    fn step(&mut self, dt: f64) -> Option<Telemetry> {
+        self.elapsed += dt;
+        if self.elapsed >= self.manoeuvre.duration() {
+            self.next_manoeuvre();
+        }
+        self.fly(dt as f32);
+
+        if self.dropout > 0.0 {
+            self.dropout -= dt;
+            return None;
+        }
+        if self.rng.uniform() < self.dropouts / 60.0 * dt {
+            self.dropout = self.rng.range(1.0, 5.0);
+            debug!(id = %self.id, seconds = self.dropout, "simulated dropout");
+            return None;
+        }
+
+        Some(self.telemetry())
+    }
+
+    fn fly(&mut self, dt: f32) {
+        let progress = (self.elapsed / self.manoeuvre.duration()).min(1.0) as f32;
+        let state = &mut self.state;
+
+        let (target_roll, target_pitch) = match self.manoeuvre {
+            Manoeuvre::Cruise { .. } => (0.0, 0.0),
+            Manoeuvre::Turn { bank, .. } => (bank, 0.0),
+            Manoeuvre::Climb { pitch, .. } => (0.0, pitch),
+            Manoeuvre::Roll { .. } | Manoeuvre::Loop { .. } => (state.roll, state.pitch),
+        };
+        state.roll = approach(state.roll, target_roll, ROLL_RATE * dt);
+        state.pitch = approach(state.pitch, target_pitch, PITCH_RATE * dt);
+
+        match self.manoeuvre {
+            Manoeuvre::Roll { direction, .. } => {
+                state.roll = wrap(self.entry.roll + direction * 360.0 * progress);
+            }
+            Manoeuvre::Loop { .. } => {
+                // Over the top the nose points back, upside down
+                let angle = (self.entry.pitch + 360.0 * progress).to_radians();
+                let inverted = angle.cos() < 0.0;
+
+                state.pitch = angle.sin().asin().to_degrees();
+                state.roll = if inverted { 180.0 } else { 0.0 };
+                state.heading =
+                    (self.entry.heading + if inverted { 180.0 } else { 0.0 }).rem_euclid(360.0);
+            }
+            _ => {
+                // Coordinated turn, up to a knife edge
+                let bank = state.roll.clamp(-80.0, 80.0).to_radians();
+                let turn_rate = (GRAVITY * bank.tan() / state.airspeed).to_degrees();
+
+                state.heading = (state.heading + turn_rate * dt).rem_euclid(360.0);
+                // Turns bleed some speed
+                let target_airspeed = CRUISE_AIRSPEED * (1.0 - 0.15 * bank.sin().abs());
+                state.airspeed = approach(state.airspeed, target_airspeed, 2.0 * dt);
+            }
+        }
+
+        let pitch = state.pitch.to_radians();
+        let heading = f64::from(state.heading).to_radians();
+        let horizontal = f64::from(state.airspeed * pitch.cos() * dt);
+
+        state.vertical_speed = state.airspeed * pitch.sin();
+        state.altitude += state.vertical_speed * dt;
+        state.position.latitude += horizontal * heading.cos() / METERS_PER_DEGREE;
+        state.position.longitude += horizontal * heading.sin()
+            / (METERS_PER_DEGREE * state.position.latitude.to_radians().cos());
+        state.battery = (state.battery - 0.02 * dt).max(0.0);
+    }
+
+    /// The current state as a sensor would report it
+    fn telemetry(&mut self) -> Telemetry {
+        let state = self.state;
+        let noise = self.noise;
+        let mut noisy = |value: f32, scale: f32| value + self.rng.normal() as f32 * noise * scale;
+
+        Telemetry {
+            heading: Some(noisy(state.heading, 1.0).rem_euclid(360.0)),
+            pitch: Some(noisy(state.pitch, 1.0).clamp(-90.0, 90.0)),
+            roll: Some(wrap(noisy(state.roll, 1.0))),
+            position: Some(state.position),
+            altitude: Some(noisy(HOME_ALTITUDE + state.altitude, 0.5)),
+            relative_altitude: Some(noisy(state.altitude, 0.5)),
+            airspeed: Some(noisy(state.airspeed, 0.2)),
+            groundspeed: Some(state.airspeed * state.pitch.to_radians().cos()),
+            vertical_speed: Some(noisy(state.vertical_speed, 0.2)),
+            battery: Some(Battery {
+                // A 4S pack
+                voltage: Some(14.0 + 2.8 * state.battery / 100.0),
+                current: Some(8.0 + 0.2 * state.pitch.max(0.0)),
+                remaining: Some(state.battery),
+            }),
+            gps: Some(Gps {
+                fix: GpsFix::Fix3D,
+                satellites: Some(12),
+            }),
+            flight_mode: Some(self.manoeuvre.name().to_owned()),
+            armed: Some(true),
+            ..Default::default()
+        }
+    }
+}
+
+/// Move `value` towards `target` by at most `step`
+fn approach(value: f32, target: f32, step: f32) -> f32 {
+    value + (target - value).clamp(-step, step)
+}
+
+/// Wrap an angle in degrees to -180-180
+fn wrap(degrees: f32) -> f32 {
+    (degrees + 180.0).rem_euclid(360.0) - 180.0
+}
+
+/// A small xorshift generator, so a seed replays the same flight on every platform
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        // The state must never be zero
+        Self(seed ^ 0x9e37_79b9_7f4a_7c15)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+
+        self.0
+    }
+
+    /// Uniformly distributed in 0-1
+    fn uniform(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    fn range(&mut self, low: f64, high: f64) -> f64 {
+        low + (high - low) * self.uniform()
+    }
+
+    fn below(&mut self, n: u64) -> u64 {
+        self.next_u64() % n
+    }
+
+    /// Normally distributed with a standard deviation of 1
+    fn normal(&mut self) -> f64 {
+        // Box-Muller
+        let u = 1.0 - self.uniform();
+        let v = self.uniform();
+
+        (-2.0 * u.ln()).sqrt() * (2.0 * PI * v).cos()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DT: f64 = 0.1;
+
+    fn config(scenario: Scenario) -> SimulatorConfig {
+        SimulatorConfig {
+            scenario,
+            noise: 0.0,
+            seed: Some(1),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn script_stays_in_range() {
+        let mut vehicle = SimulatedVehicle::new(&config(Scenario::Scripted), 0, 1);
+        let script_duration = SCRIPT
+            .iter()
+            .map(|manoeuvre| manoeuvre.duration())
+            .sum::<f64>();
+        let mut inverted = false;
+
+        for _ in 0..(script_duration / DT) as usize {
+            let telemetry = vehicle.step(DT).expect("no dropouts");
+            let (heading, pitch, roll) = (
+                telemetry.heading.unwrap(),
+                telemetry.pitch.unwrap(),
+                telemetry.roll.unwrap(),
+            );
+
+            assert!((0.0..360.0).contains(&heading), "heading {heading}");
+            assert!((-90.0..=90.0).contains(&pitch), "pitch {pitch}");
+            assert!((-180.0..=180.0).contains(&roll), "roll {roll}");
+            inverted |= roll.abs() > 170.0;
+        }
+
+        assert!(inverted, "the script flies upside down");
+        // Every climb is matched by a descent, and loops end where they started
+        assert!(
+            (vehicle.state.altitude - START_ALTITUDE).abs() < 20.0,
+            "ended at {} m",
+            vehicle.state.altitude
+        );
+    }
+
+    #[test]
+    fn loop_goes_over_the_top() {
+        let mut vehicle = SimulatedVehicle::new(&config(Scenario::Scripted), 0, 1);
+        vehicle.manoeuvre = Manoeuvre::Loop { duration: 8.0 };
+        vehicle.entry = vehicle.state;
+        let entry_heading = vehicle.state.heading;
+
+        let mut attitudes = Vec::new();
+        for _ in 0..79 {
+            vehicle.elapsed += DT;
+            vehicle.fly(DT as f32);
+            attitudes.push((
+                vehicle.state.heading,
+                vehicle.state.pitch,
+                vehicle.state.roll,
+            ));
+        }
+
+        // A quarter of the way round the nose points straight up, halfway it is upside down
+        let (_, pitch, _) = attitudes[19];
+        assert!(pitch > 85.0, "pitch {pitch} a quarter of the way");
+        let (heading, pitch, roll) = attitudes[39];
+        assert!(pitch.abs() < 5.0, "pitch {pitch} halfway");
+        assert_eq!(roll, 180.0);
+        assert_eq!(heading, (entry_heading + 180.0).rem_euclid(360.0));
+        let (heading, pitch, roll) = attitudes[78];
+        assert!(pitch.abs() < 5.0 && roll == 0.0 && heading == entry_heading);
+    }
+
+    #[test]
+    fn random_is_seeded() {
+        let fly = || {
+            let mut vehicle = SimulatedVehicle::new(
+                &SimulatorConfig {
+                    noise: 1.0,
+                    dropouts: 10.0,
+                    ..config(Scenario::Random)
+                },
+                0,
+                7,
+            );
+
+            (0..1000).map(|_| vehicle.step(DT)).collect::<Vec<_>>()
+        };
+
+        let flight = fly();
+        assert_eq!(flight, fly());
+        assert!(flight.iter().any(Option::is_none), "drops out");
+        assert!(flight.iter().any(Option::is_some));
+    }
+}
@@ -0,0 +1,133 @@
+//! Newline delimited JSON telemetry over plain TCP, for anything that can open a socket
+
+use std::{
+    collections::HashMap,
+    io::{BufRead, BufReader, Read},
+    net::{TcpListener, TcpStream},
+    thread,
+};
+
+use tracing::{debug, trace, warn};
+
+use super::{addressed_vehicle, bind_retrying, Ingest, MAX_LINE_LEN};
+use crate::{config::TcpConfig, vehicle::VehicleId};
+
+const LISTENER: &str = "TCP";
+
+pub fn tcp_thread(config: TcpConfig, ingest: Ingest) {
+    let address = config.address();
+
+    let server = bind_retrying(&ingest, LISTENER, &format!("tcp://{address}"), || {
+        TcpListener::bind(address)
+    });
+
+    for stream in server.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(error) => {
+                warn!(%error, "failed to accept TCP connection");
+                ingest.listener(LISTENER, |status| status.handshake_failed(&error));
+                continue;
+            }
+        };
+
+        thread::spawn({
+            let ingest = ingest.clone();
+
+            move || connection_thread(stream, ingest)
+        });
+    }
+}
+
+fn connection_thread(stream: TcpStream, ingest: Ingest) {
+    let peer = match stream.peer_addr() {
+        Ok(peer) => peer,
+        Err(error) => {
+            warn!(%error, "TCP connection without peer address");
+            ingest.listener(LISTENER, |status| status.handshake_failed(&error));
+            return;
+        }
+    };
+    trace!(%peer, "new line-delimited TCP connection");
+
+    // A single stream may multiplex several vehicles through the `vehicle` field
+    let mut connections = HashMap::new();
+
+    let mut reader = BufReader::new(stream);
+    let mut line = Vec::new();
+
+    loop {
+        line.clear();
+
+        // One byte over the limit is enough to tell the line is too long
+        match (&mut reader)
+            .take(MAX_LINE_LEN as u64 + 1)
+            .read_until(b'\n', &mut line)
+        {
+            Ok(0) => break,
+            Ok(_) if line.len() > MAX_LINE_LEN && !line.ends_with(b"\n") => {
+                warn!(%peer, "closing TCP connection sending a line over {MAX_LINE_LEN} bytes");
+                break;
+            }
+            Ok(_) => {}
+            Err(error) => {
+                debug!(%error, %peer, "error in TCP connection");
+                break;
+            }
+        }
+
+        let line = String::from_utf8_lossy(&line);
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let id = addressed_vehicle(line).unwrap_or_else(|| VehicleId::from_peer(peer));
+        let connection = *connections
+            .entry(id.clone())
+            .or_insert_with(|| ingest.connected(&id, "tcp", peer.to_string()));
+
+        ingest.json(&id, connection, line);
+    }
+
+    for (id, connection) in connections {
+        ingest.disconnected(&id, connection);
+    }
+
+    trace!(%peer, "TCP connection closed");
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::*;
+    use crate::{
+        calibration::Calibrations, recording::Recorder, status::Status, vehicle::Vehicles,
+    };
+
+    #[test]
+    fn line_too_long() {
+        let (mut vehicles, updates) = Vehicles::new(Calibrations::default());
+        let ingest = Ingest::new(updates, Recorder::default(), Status::default(), || {});
+
+        let server = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut client = TcpStream::connect(server.local_addr().unwrap()).unwrap();
+        let (stream, _) = server.accept().unwrap();
+        let reader = thread::spawn(move || connection_thread(stream, ingest));
+
+        client
+            .write_all(b"{\"vehicle\": \"tcp\", \"heading\": 90.0}\n")
+            .unwrap();
+        client.write_all(&vec![b' '; MAX_LINE_LEN + 1]).unwrap();
+        // Never read, the connection is already closed
+        let _ = client.write_all(b"{\"vehicle\": \"tcp\", \"heading\": 180.0}\n");
+
+        reader.join().unwrap();
+        vehicles.drain();
+
+        let vehicle = &vehicles[&VehicleId::new("tcp")];
+        assert_eq!(vehicle.telemetry.heading, Some(90.0));
+        assert!(vehicle.connections.is_empty());
+    }
+}
@@ -0,0 +1,74 @@
+//! JSON telemetry in UDP datagrams, for microcontrollers that can not keep a socket open
+//!
+//! Every datagram carries one or more messages, one per line.
+
+use std::{io, net::UdpSocket, time::Duration};
+
+use tracing::{debug, trace, warn};
+
+use super::{addressed_vehicle, bind_retrying, hex_dump, Ingest, Sessions};
+use crate::{config::UdpConfig, vehicle::VehicleId};
+
+/// A sender is considered gone after sending nothing for this long
+const UDP_TIMEOUT: Duration = Duration::from_secs(5);
+
+pub fn udp_thread(config: UdpConfig, ingest: Ingest) {
+    let address = config.address();
+
+    let socket = bind_retrying(&ingest, "UDP", &format!("udp://{address}"), || {
+        let socket = UdpSocket::bind(address)?;
+        socket.set_read_timeout(Some(Duration::from_secs(1)))?;
+
+        Ok(socket)
+    });
+
+    let mut sessions = Sessions::new(UDP_TIMEOUT);
+    let mut buffer = [0; 65536];
+
+    loop {
+        match socket.recv_from(&mut buffer) {
+            Ok((len, peer)) => {
+                trace!(len, %peer, "udp datagram");
+
+                let mut heard = |id: &VehicleId| {
+                    sessions.heard(id.clone(), || {
+                        debug!(%id, %peer, "udp sender appeared");
+
+                        ingest.connected(id, "udp", peer.to_string())
+                    })
+                };
+
+                let datagram = match std::str::from_utf8(&buffer[..len]) {
+                    Ok(datagram) => datagram,
+                    Err(error) => {
+                        debug!(%peer, %error, "udp datagram is not UTF-8");
+                        let id = VehicleId::from_peer(peer);
+                        let connection = heard(&id);
+                        ingest.parse_failed(&id, connection, error, hex_dump(&buffer[..len]));
+                        continue;
+                    }
+                };
+
+                for line in datagram.lines().filter(|line| !line.trim().is_empty()) {
+                    let id = addressed_vehicle(line).unwrap_or_else(|| VehicleId::from_peer(peer));
+                    let connection = heard(&id);
+
+                    ingest.json(&id, connection, line);
+                }
+            }
+            Err(error)
+                if matches!(
+                    error.kind(),
+                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                ) => {}
+            Err(error) => {
+                warn!(%error, "error receiving udp datagram");
+            }
+        }
+
+        sessions.expire(|id, connection| {
+            debug!(%id, "udp sender timed out");
+            ingest.disconnected(id, connection);
+        });
+    }
+}
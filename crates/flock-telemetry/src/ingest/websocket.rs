@@ -0,0 +1,276 @@
+//! Websocket server phones connect to, which also serves them the sensor page
+
+use std::{
+    io::{self, Read, Write},
+    net::{IpAddr, Ipv4Addr, SocketAddr, TcpListener, TcpStream, UdpSocket},
+    sync::Arc,
+    thread,
+    time::Duration,
+};
+
+use native_tls::TlsAcceptor;
+use tracing::{debug, error, trace, warn};
+use tungstenite::{
+    handshake::server::{Request, Response},
+    Message,
+};
+
+use super::{bind_retrying, Ingest};
+use crate::{config::ServerConfig, http, vehicle::VehicleId};
+
+const LISTENER: &str = "Websocket";
+
+/// Longest a command waits to be sent while the client sends nothing
+const COMMAND_POLL: Duration = Duration::from_millis(100);
+
+/// Longest a client may stall the TLS handshake, HTTP request or websocket upgrade, so idle
+/// sockets do not each hold on to a thread forever
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+pub fn websocket_thread(config: ServerConfig, ingest: Ingest) {
+    let address = config.address();
+
+    // A bad certificate or key does not fix itself, unlike a port in use
+    let tls = match config.tls.as_ref().map(|tls| tls.acceptor()).transpose() {
+        Ok(tls) => tls.map(Arc::new),
+        Err(error) => {
+            error!(%error, "TLS config invalid, not serving websockets");
+            ingest.listener(LISTENER, |status| {
+                status.config_invalid(format!("TLS config invalid: {error}"))
+            });
+            return;
+        }
+    };
+
+    let server = bind_retrying(
+        &ingest,
+        LISTENER,
+        &format!("{}://{address}", config.scheme()),
+        || TcpListener::bind(address),
+    );
+
+    ingest.status().lock().phone_page = Some(format!(
+        "{}://{}/",
+        if config.tls.is_some() {
+            "https"
+        } else {
+            "http"
+        },
+        SocketAddr::new(lan_address(config.bind), config.port)
+    ));
+    ingest.request_repaint();
+
+    for stream in server.incoming() {
+        trace!("new TCP connection");
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(error) => {
+                warn!(%error, "failed to accept TCP connection");
+                ingest.listener(LISTENER, |status| status.handshake_failed(&error));
+                continue;
+            }
+        };
+
+        thread::spawn({
+            let ingest = ingest.clone();
+            let tls = tls.clone();
+
+            move || connection_thread(stream, tls, ingest)
+        });
+    }
+}
+
+fn connection_thread(stream: TcpStream, tls: Option<Arc<TlsAcceptor>>, ingest: Ingest) {
+    let (peer, socket) = match stream.peer_addr().and_then(|peer| {
+        stream.set_read_timeout(Some(HANDSHAKE_TIMEOUT))?;
+        stream.set_write_timeout(Some(HANDSHAKE_TIMEOUT))?;
+
+        Ok((peer, stream.try_clone()?))
+    }) {
+        Ok(peer) => peer,
+        Err(error) => {
+            warn!(%error, "failed to set up TCP connection");
+            ingest.listener(LISTENER, |status| status.handshake_failed(&error));
+            return;
+        }
+    };
+
+    match tls {
+        Some(tls) => match tls.accept(stream) {
+            Ok(stream) => websocket_session(stream, socket, true, peer, ingest),
+            Err(error) => {
+                warn!(%error, %peer, "TLS handshake failed");
+                ingest.listener(LISTENER, |status| {
+                    status.handshake_failed(format!("{peer}: {error}"))
+                });
+            }
+        },
+        None => websocket_session(stream, socket, false, peer, ingest),
+    }
+}
+
+/// Serve a single client on `stream`, which reads and writes through `socket`
+// The handshake callback signature is dictated by tungstenite
+#[allow(clippy::result_large_err)]
+fn websocket_session(
+    mut stream: impl Read + Write,
+    socket: TcpStream,
+    tls: bool,
+    peer: SocketAddr,
+    ingest: Ingest,
+) {
+    // Browsers opening the server address get the phone page, only upgrades become vehicles
+    let head = match http::read_request(&mut stream) {
+        Ok((http::Request::Upgrade, head)) => head,
+        Ok((http::Request::Get { path, host }, _)) => {
+            trace!(%peer, path, "serving phone page");
+            if let Err(error) = http::serve_phone_page(&mut stream, &path, host.as_deref(), tls) {
+                debug!(%error, %peer, "failed to serve phone page");
+            }
+            return;
+        }
+        Ok((http::Request::Other { method }, _)) => {
+            debug!(%peer, method, "unsupported HTTP request");
+            let _ = http::respond(
+                &mut stream,
+                "405 Method Not Allowed",
+                "text/plain",
+                "Method Not Allowed",
+            );
+            return;
+        }
+        Err(error) => {
+            warn!(%error, %peer, "failed to read HTTP request");
+            ingest.listener(LISTENER, |status| {
+                status.handshake_failed(format!("{peer}: {error}"))
+            });
+            return;
+        }
+    };
+
+    let mut requested_id = None;
+    let stream = http::Rewind::new(head, stream);
+    let websocket = tungstenite::accept_hdr(stream, |request: &Request, response: Response| {
+        requested_id = vehicle_id_from_query(request.uri().query());
+
+        Ok(response)
+    });
+    let mut websocket = match websocket {
+        Ok(websocket) => websocket,
+        Err(error) => {
+            warn!(%error, %peer, "websocket handshake failed");
+            ingest.listener(LISTENER, |status| {
+                status.handshake_failed(format!("{peer}: {error}"))
+            });
+            return;
+        }
+    };
+
+    let id = requested_id.unwrap_or_else(|| VehicleId::from_peer(peer));
+    trace!(%id, %peer, "TCP upgraded to websocket connection");
+
+    let (connection, commands) = ingest.connected_with_commands(&id, "websocket", peer.to_string());
+
+    // Wake up regularly to send commands, even when the client is quiet, instead of the
+    // handshake timeout
+    if let Err(error) = socket.set_read_timeout(Some(COMMAND_POLL)) {
+        warn!(%id, %error, "failed to set read timeout, commands wait for the next message");
+    }
+    if let Err(error) = socket.set_write_timeout(None) {
+        warn!(%id, %error, "failed to clear the handshake write timeout");
+    }
+
+    'session: loop {
+        match websocket.read() {
+            Ok(Message::Close(_)) => {
+                break;
+            }
+            Ok(Message::Binary(bytes)) => {
+                ingest.binary(&id, connection, &bytes);
+            }
+            Ok(Message::Text(text)) => {
+                ingest.json(&id, connection, text.as_str());
+            }
+            Ok(_) => {}
+            Err(tungstenite::Error::Io(error))
+                if matches!(
+                    error.kind(),
+                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                ) => {}
+            Err(tungstenite::Error::ConnectionClosed) => {
+                break;
+            }
+            Err(tungstenite::Error::Protocol(
+                tungstenite::error::ProtocolError::ResetWithoutClosingHandshake,
+            )) => {
+                debug!(%id, "reset without closing handshake. Likely iOS tab unfocused");
+
+                break;
+            }
+            Err(error) => {
+                warn!(%id, %error, "error in websocket connection");
+                break;
+            }
+        }
+
+        for command in commands.try_iter() {
+            trace!(%id, ?command, "sending command");
+            let text = serde_json::to_string(&command).expect("commands serialize to JSON");
+
+            if let Err(error) = websocket.send(Message::text(text)) {
+                warn!(%id, %error, "failed to send command");
+                break 'session;
+            }
+        }
+    }
+
+    ingest.disconnected(&id, connection);
+
+    trace!(%id, "websocket connection closed");
+}
+
+/// Address other devices on the network can likely reach us on
+///
+/// When bound to the unspecified address, this is the address of the interface with
+/// the default route, found by "connecting" a UDP socket (which sends nothing).
+fn lan_address(bind: IpAddr) -> IpAddr {
+    if !bind.is_unspecified() {
+        return bind;
+    }
+
+    UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))
+        .and_then(|socket| {
+            socket.connect((Ipv4Addr::new(192, 0, 2, 1), 9))?;
+            socket.local_addr()
+        })
+        .map(|address| address.ip())
+        .unwrap_or(IpAddr::V4(Ipv4Addr::LOCALHOST))
+}
+
+/// Extract the `vehicle` parameter from a handshake query string such as `vehicle=drone-1`
+fn vehicle_id_from_query(query: Option<&str>) -> Option<VehicleId> {
+    let vehicle = http::query_parameter(query?, "vehicle")?;
+    let vehicle = vehicle.trim();
+
+    (!vehicle.is_empty()).then(|| VehicleId::new(vehicle))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn vehicle_from_query() {
+        let id = |query| vehicle_id_from_query(query).map(|id| id.as_str().to_owned());
+
+        assert_eq!(id(Some("vehicle=drone-1")).as_deref(), Some("drone-1"));
+        // As the phone page sends it, through `URLSearchParams`
+        assert_eq!(
+            id(Some("mount=flat&vehicle=Bob%27s+phone")).as_deref(),
+            Some("Bob's phone")
+        );
+        assert_eq!(id(Some("vehicle=+")), None);
+        assert_eq!(id(Some("mount=flat")), None);
+        assert_eq!(id(None), None);
+    }
+}
@@ -0,0 +1,28 @@
+//! The telemetry model of Flock, and the ingest that fills it from vehicles
+//!
+//! [`telemetry`] and [`orientation`] make up the data model shared with the instruments. The
+//! rest needs the default `ingest` feature: the listeners, and the vehicle registry they feed.
+
+pub mod orientation;
+pub mod telemetry;
+
+#[cfg(feature = "ingest")]
+pub mod calibration;
+#[cfg(feature = "ingest")]
+pub mod command;
+#[cfg(feature = "ingest")]
+pub mod config;
+#[cfg(feature = "ingest")]
+pub mod connection;
+#[cfg(feature = "ingest")]
+pub mod http;
+#[cfg(feature = "ingest")]
+pub mod ingest;
+#[cfg(feature = "ingest")]
+pub mod mavlink;
+#[cfg(feature = "ingest")]
+pub mod recording;
+#[cfg(feature = "ingest")]
+pub mod status;
+#[cfg(feature = "ingest")]
+pub mod vehicle;
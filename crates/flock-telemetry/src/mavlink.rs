@@ -0,0 +1,567 @@
+//! Minimal MAVLink v1/v2 decoder for the handful of messages Flock displays
+//!
+//! Only the framing and the messages listed in [`Message`] are supported. Frames
+//! for any other message id are skipped, since their checksum can not be
+//! verified without the message's `CRC_EXTRA` seed.
+
+use tracing::trace;
+
+use crate::telemetry::{Position, Telemetry};
+
+const MAGIC_V1: u8 = 0xFE;
+const MAGIC_V2: u8 = 0xFD;
+
+const HEADER_LEN_V1: usize = 6;
+const HEADER_LEN_V2: usize = 10;
+const CHECKSUM_LEN: usize = 2;
+const SIGNATURE_LEN: usize = 13;
+
+const INCOMPAT_FLAG_SIGNED: u8 = 0x01;
+
+/// `MAV_MODE_FLAG_SAFETY_ARMED` in `HEARTBEAT.base_mode`
+const MODE_FLAG_SAFETY_ARMED: u8 = 0x80;
+
+/// A single validated MAVLink frame
+#[derive(Debug, Clone)]
+pub struct Frame {
+    pub system_id: u8,
+    pub component_id: u8,
+    pub message_id: u32,
+    pub payload: Vec<u8>,
+    /// Length of the whole frame on the wire
+    pub len: usize,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Message {
+    Heartbeat(Heartbeat),
+    Attitude(Attitude),
+    VfrHud(VfrHud),
+    GlobalPositionInt(GlobalPositionInt),
+}
+
+/// `HEARTBEAT` (#0)
+#[derive(Debug, Clone, Copy)]
+pub struct Heartbeat {
+    pub custom_mode: u32,
+    pub mav_type: u8,
+    pub autopilot: u8,
+    pub base_mode: u8,
+    pub system_status: u8,
+}
+
+/// `ATTITUDE` (#30), angles in radians
+#[derive(Debug, Clone, Copy)]
+pub struct Attitude {
+    pub time_boot_ms: u32,
+    pub roll: f32,
+    pub pitch: f32,
+    pub yaw: f32,
+    pub roll_speed: f32,
+    pub pitch_speed: f32,
+    pub yaw_speed: f32,
+}
+
+/// `VFR_HUD` (#74)
+#[derive(Debug, Clone, Copy)]
+pub struct VfrHud {
+    /// m/s
+    pub airspeed: f32,
+    /// m/s
+    pub groundspeed: f32,
+    /// m (MSL)
+    pub altitude: f32,
+    /// m/s
+    pub climb: f32,
+    /// degrees, 0..360
+    pub heading: i16,
+    /// percent
+    pub throttle: u16,
+}
+
+/// `GLOBAL_POSITION_INT` (#33), in the raw integer units of the wire format
+#[derive(Debug, Clone, Copy)]
+pub struct GlobalPositionInt {
+    pub time_boot_ms: u32,
+    /// degE7
+    pub lat: i32,
+    /// degE7
+    pub lon: i32,
+    /// mm (MSL)
+    pub alt: i32,
+    /// mm above home
+    pub relative_alt: i32,
+    /// cm/s
+    pub vx: i16,
+    /// cm/s
+    pub vy: i16,
+    /// cm/s
+    pub vz: i16,
+    /// cdeg, `u16::MAX` when unknown
+    pub hdg: u16,
+}
+
+impl GlobalPositionInt {
+    /// Heading in degrees, if the autopilot knows it
+    pub fn heading(&self) -> Option<f32> {
+        (self.hdg != u16::MAX).then(|| f32::from(self.hdg) / 100.0)
+    }
+}
+
+/// `(message id, full payload length, CRC_EXTRA)` of every supported message
+const MESSAGES: &[(u32, usize, u8)] = &[
+    (0, 9, 50),    // HEARTBEAT
+    (30, 28, 39),  // ATTITUDE
+    (33, 28, 104), // GLOBAL_POSITION_INT
+    (74, 20, 20),  // VFR_HUD
+];
+
+fn message_info(message_id: u32) -> Option<(usize, u8)> {
+    MESSAGES
+        .iter()
+        .find(|(id, _, _)| *id == message_id)
+        .map(|(_, len, crc_extra)| (*len, *crc_extra))
+}
+
+impl Message {
+    pub fn decode(frame: &Frame) -> Option<Self> {
+        let (len, _) = message_info(frame.message_id)?;
+
+        // MAVLink 2 truncates trailing zero bytes from the payload
+        let mut payload = frame.payload.clone();
+        payload.resize(len, 0);
+        let mut reader = PayloadReader(&payload);
+
+        Some(match frame.message_id {
+            0 => Message::Heartbeat(Heartbeat {
+                custom_mode: reader.u32(),
+                mav_type: reader.u8(),
+                autopilot: reader.u8(),
+                base_mode: reader.u8(),
+                system_status: reader.u8(),
+            }),
+            30 => Message::Attitude(Attitude {
+                time_boot_ms: reader.u32(),
+                roll: reader.f32(),
+                pitch: reader.f32(),
+                yaw: reader.f32(),
+                roll_speed: reader.f32(),
+                pitch_speed: reader.f32(),
+                yaw_speed: reader.f32(),
+            }),
+            33 => Message::GlobalPositionInt(GlobalPositionInt {
+                time_boot_ms: reader.u32(),
+                lat: reader.i32(),
+                lon: reader.i32(),
+                alt: reader.i32(),
+                relative_alt: reader.i32(),
+                vx: reader.i16(),
+                vy: reader.i16(),
+                vz: reader.i16(),
+                hdg: reader.u16(),
+            }),
+            74 => Message::VfrHud(VfrHud {
+                airspeed: reader.f32(),
+                groundspeed: reader.f32(),
+                altitude: reader.f32(),
+                climb: reader.f32(),
+                heading: reader.i16(),
+                throttle: reader.u16(),
+            }),
+            _ => unreachable!("message id is listed in MESSAGES"),
+        })
+    }
+
+    /// The part of the telemetry model this message carries
+    pub fn telemetry(&self) -> Telemetry {
+        match *self {
+            Message::Heartbeat(heartbeat) => Telemetry {
+                armed: Some(heartbeat.base_mode & MODE_FLAG_SAFETY_ARMED != 0),
+                ..Default::default()
+            },
+            Message::Attitude(attitude) => Telemetry {
+                heading: Some(attitude.yaw.to_degrees().rem_euclid(360.0)),
+                pitch: Some(attitude.pitch.to_degrees()),
+                roll: Some(attitude.roll.to_degrees()),
+                ..Default::default()
+            },
+            Message::VfrHud(hud) => Telemetry {
+                heading: Some(f32::from(hud.heading)),
+                airspeed: Some(hud.airspeed),
+                groundspeed: Some(hud.groundspeed),
+                altitude: Some(hud.altitude),
+                vertical_speed: Some(hud.climb),
+                ..Default::default()
+            },
+            Message::GlobalPositionInt(position) => Telemetry {
+                heading: position.heading(),
+                position: Some(Position {
+                    latitude: f64::from(position.lat) / 1e7,
+                    longitude: f64::from(position.lon) / 1e7,
+                }),
+                altitude: Some(position.alt as f32 / 1000.0),
+                relative_altitude: Some(position.relative_alt as f32 / 1000.0),
+                groundspeed: Some(f32::hypot(position.vx.into(), position.vy.into()) / 100.0),
+                // NED, so down is positive
+                vertical_speed: Some(-f32::from(position.vz) / 100.0),
+                ..Default::default()
+            },
+        }
+    }
+}
+
+struct PayloadReader<'a>(&'a [u8]);
+
+impl PayloadReader<'_> {
+    fn take<const N: usize>(&mut self) -> [u8; N] {
+        let (head, tail) = self.0.split_at(N);
+        self.0 = tail;
+
+        head.try_into().expect("split_at returns exactly N bytes")
+    }
+
+    fn u8(&mut self) -> u8 {
+        u8::from_le_bytes(self.take())
+    }
+
+    fn u16(&mut self) -> u16 {
+        u16::from_le_bytes(self.take())
+    }
+
+    fn i16(&mut self) -> i16 {
+        i16::from_le_bytes(self.take())
+    }
+
+    fn u32(&mut self) -> u32 {
+        u32::from_le_bytes(self.take())
+    }
+
+    fn i32(&mut self) -> i32 {
+        i32::from_le_bytes(self.take())
+    }
+
+    fn f32(&mut self) -> f32 {
+        f32::from_le_bytes(self.take())
+    }
+}
+
+/// Incremental frame decoder, feed it bytes as they arrive from any transport
+#[derive(Debug, Default)]
+pub struct Decoder {
+    buffer: Vec<u8>,
+}
+
+impl Decoder {
+    pub fn push(&mut self, bytes: &[u8]) {
+        self.buffer.extend_from_slice(bytes);
+    }
+
+    /// Pop the next valid frame out of the buffered bytes, skipping garbage,
+    /// frames with bad checksums and frames of unsupported messages
+    pub fn next_frame(&mut self) -> Option<Frame> {
+        loop {
+            let start = self
+                .buffer
+                .iter()
+                .position(|byte| *byte == MAGIC_V1 || *byte == MAGIC_V2);
+            let Some(start) = start else {
+                self.buffer.clear();
+                return None;
+            };
+            self.buffer.drain(..start);
+
+            match self.try_frame() {
+                // Need more bytes
+                Err(None) => return None,
+                // Throw away the frame or the false magic byte
+                Err(Some(skip)) => {
+                    self.buffer.drain(..skip);
+                }
+                Ok((frame, len)) => {
+                    self.buffer.drain(..len);
+                    return Some(frame);
+                }
+            }
+        }
+    }
+
+    /// Parse the frame at the start of the buffer
+    ///
+    /// On failure returns how many bytes to skip, or `None` if the frame is incomplete.
+    fn try_frame(&self) -> Result<(Frame, usize), Option<usize>> {
+        let buffer = &self.buffer;
+        let payload_len = usize::from(*buffer.get(1).ok_or(None)?);
+
+        let (header_len, system_id, component_id, message_id, signed) = if buffer[0] == MAGIC_V1 {
+            let header = buffer.get(..HEADER_LEN_V1).ok_or(None)?;
+
+            (
+                HEADER_LEN_V1,
+                header[3],
+                header[4],
+                u32::from(header[5]),
+                false,
+            )
+        } else {
+            let header = buffer.get(..HEADER_LEN_V2).ok_or(None)?;
+            let message_id = u32::from_le_bytes([header[7], header[8], header[9], 0]);
+
+            (
+                HEADER_LEN_V2,
+                header[5],
+                header[6],
+                message_id,
+                header[2] & INCOMPAT_FLAG_SIGNED != 0,
+            )
+        };
+
+        let info = message_info(message_id);
+
+        // Most likely a false magic byte, no need to wait for the rest of it
+        if info.is_some_and(|(full_len, _)| payload_len > full_len) {
+            return Err(Some(1));
+        }
+
+        let frame_len =
+            header_len + payload_len + CHECKSUM_LEN + if signed { SIGNATURE_LEN } else { 0 };
+        let frame = buffer.get(..frame_len).ok_or(None)?;
+
+        let Some((_, crc_extra)) = info else {
+            trace!(message_id, "skipping unsupported mavlink message");
+            return Err(Some(frame_len));
+        };
+
+        let checksummed = &frame[1..header_len + payload_len];
+        let checksum_bytes = &frame[header_len + payload_len..][..CHECKSUM_LEN];
+        let checksum = u16::from_le_bytes([checksum_bytes[0], checksum_bytes[1]]);
+
+        if crc(checksummed, crc_extra) != checksum {
+            trace!(message_id, "mavlink checksum mismatch");
+            return Err(Some(1));
+        }
+
+        Ok((
+            Frame {
+                system_id,
+                component_id,
+                message_id,
+                payload: frame[header_len..][..payload_len].to_vec(),
+                len: frame_len,
+            },
+            frame_len,
+        ))
+    }
+}
+
+/// CRC-16/MCRF4XX as used by MAVLink, seeded with the message's `CRC_EXTRA`
+fn crc(bytes: &[u8], crc_extra: u8) -> u16 {
+    bytes
+        .iter()
+        .chain(std::iter::once(&crc_extra))
+        .fold(0xFFFF, |crc, byte| {
+            let tmp = byte ^ (crc & 0xFF) as u8;
+            let tmp = tmp ^ (tmp << 4);
+            let tmp = u16::from(tmp);
+
+            (crc >> 8) ^ (tmp << 8) ^ (tmp << 3) ^ (tmp >> 4)
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `HEARTBEAT` v1 from system 1: quadrotor, ArduPilot, armed, active
+    #[rustfmt::skip]
+    const HEARTBEAT_V1: &[u8] = &[
+        0xFE, 0x09, 0x00, 0x01, 0x01, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x02, 0x03, 0x81, 0x04, 0x03,
+        0x05, 0x25,
+    ];
+    /// `ATTITUDE` v2: roll 0.5 rad, pitch -0.25 rad, yaw -1.5 rad
+    #[rustfmt::skip]
+    const ATTITUDE_V2: &[u8] = &[
+        0xFD, 0x1C, 0x00, 0x00, 0x01, 0x01, 0x01, 0x1E, 0x00, 0x00,
+        0xE8, 0x03, 0x00, 0x00, 0x00, 0x00, 0x00, 0x3F, 0x00, 0x00, 0x80, 0xBE,
+        0x00, 0x00, 0xC0, 0xBF, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00,
+        0x03, 0xCF,
+    ];
+    /// `VFR_HUD` v1: 12.5 m/s airspeed, 13 m/s groundspeed, 105.5 m, -1.25 m/s, 271°, 40 %
+    #[rustfmt::skip]
+    const VFR_HUD_V1: &[u8] = &[
+        0xFE, 0x14, 0x02, 0x01, 0x01, 0x4A,
+        0x00, 0x00, 0x48, 0x41, 0x00, 0x00, 0x50, 0x41, 0x00, 0x00, 0xD3, 0x42,
+        0x00, 0x00, 0xA0, 0xBF, 0x0F, 0x01, 0x28, 0x00,
+        0xA4, 0x5F,
+    ];
+    /// `GLOBAL_POSITION_INT` v2: 51.4488° N 5.4897° E, 120 m, 20 m up, 3/4/-1 m/s, 90°
+    #[rustfmt::skip]
+    const GLOBAL_POSITION_INT_V2: &[u8] = &[
+        0xFD, 0x1C, 0x00, 0x00, 0x03, 0x01, 0x01, 0x21, 0x00, 0x00,
+        0xD0, 0x07, 0x00, 0x00, 0xC0, 0x76, 0xAA, 0x1E, 0x68, 0xA9, 0x45, 0x03,
+        0xC0, 0xD4, 0x01, 0x00, 0x20, 0x4E, 0x00, 0x00, 0x2C, 0x01, 0x90, 0x01,
+        0x9C, 0xFF, 0x28, 0x23,
+        0x49, 0x41,
+    ];
+    /// `VFR_HUD` v2 like [`VFR_HUD_V1`], but heading and throttle 0 and truncated away
+    #[rustfmt::skip]
+    const VFR_HUD_V2_TRUNCATED: &[u8] = &[
+        0xFD, 0x10, 0x00, 0x00, 0x04, 0x01, 0x01, 0x4A, 0x00, 0x00,
+        0x00, 0x00, 0x48, 0x41, 0x00, 0x00, 0x50, 0x41, 0x00, 0x00, 0xD3, 0x42,
+        0x00, 0x00, 0xA0, 0xBF,
+        0xFC, 0xF4,
+    ];
+    /// [`ATTITUDE_V2`] with the signed flag set and a signature appended
+    #[rustfmt::skip]
+    const ATTITUDE_V2_SIGNED: &[u8] = &[
+        0xFD, 0x1C, 0x01, 0x00, 0x05, 0x01, 0x01, 0x1E, 0x00, 0x00,
+        0xE8, 0x03, 0x00, 0x00, 0x00, 0x00, 0x00, 0x3F, 0x00, 0x00, 0x80, 0xBE,
+        0x00, 0x00, 0xC0, 0xBF, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00,
+        0xB8, 0x41,
+        0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0A, 0x0B, 0x0C,
+        0x0D,
+    ];
+
+    /// Every frame decoded from `bytes`, fed in one go
+    fn frames(bytes: &[u8]) -> Vec<Frame> {
+        let mut decoder = Decoder::default();
+        decoder.push(bytes);
+
+        std::iter::from_fn(|| decoder.next_frame()).collect()
+    }
+
+    /// The telemetry of the single frame in `bytes`
+    fn telemetry(bytes: &[u8]) -> Telemetry {
+        let [frame] = frames(bytes).try_into().expect("a single frame");
+        assert_eq!(frame.len, bytes.len());
+
+        Message::decode(&frame)
+            .expect("a supported message")
+            .telemetry()
+    }
+
+    fn assert_close(actual: Option<f32>, expected: f32) {
+        let actual = actual.expect("field is set");
+        assert!((actual - expected).abs() < 1e-3, "{actual} != {expected}");
+    }
+
+    #[test]
+    fn checksum() {
+        // The check value of CRC-16/MCRF4XX, with the last byte standing in for `CRC_EXTRA`
+        assert_eq!(crc(b"12345678", b'9'), 0x6F91);
+    }
+
+    #[test]
+    fn heartbeat_v1() {
+        let [frame] = frames(HEARTBEAT_V1).try_into().unwrap();
+        assert_eq!((frame.system_id, frame.component_id), (1, 1));
+
+        let Some(Message::Heartbeat(heartbeat)) = Message::decode(&frame) else {
+            panic!("not a heartbeat");
+        };
+        assert_eq!(heartbeat.mav_type, 2);
+        assert_eq!(heartbeat.autopilot, 3);
+        assert_eq!(heartbeat.system_status, 4);
+        assert_eq!(telemetry(HEARTBEAT_V1).armed, Some(true));
+    }
+
+    #[test]
+    fn attitude_v2() {
+        let telemetry = telemetry(ATTITUDE_V2);
+
+        assert_close(telemetry.roll, 0.5f32.to_degrees());
+        assert_close(telemetry.pitch, -0.25f32.to_degrees());
+        assert_close(telemetry.heading, 360.0 - 1.5f32.to_degrees());
+    }
+
+    #[test]
+    fn vfr_hud_v1() {
+        let telemetry = telemetry(VFR_HUD_V1);
+
+        assert_close(telemetry.airspeed, 12.5);
+        assert_close(telemetry.groundspeed, 13.0);
+        assert_close(telemetry.altitude, 105.5);
+        assert_close(telemetry.vertical_speed, -1.25);
+        assert_close(telemetry.heading, 271.0);
+    }
+
+    #[test]
+    fn global_position_int_v2() {
+        let telemetry = telemetry(GLOBAL_POSITION_INT_V2);
+        let position = telemetry.position.unwrap();
+
+        assert!((position.latitude - 51.4488).abs() < 1e-9);
+        assert!((position.longitude - 5.4897).abs() < 1e-9);
+        assert_close(telemetry.altitude, 120.0);
+        assert_close(telemetry.relative_altitude, 20.0);
+        assert_close(telemetry.groundspeed, 5.0);
+        assert_close(telemetry.vertical_speed, 1.0);
+        assert_close(telemetry.heading, 90.0);
+    }
+
+    #[test]
+    fn truncated_payload() {
+        let telemetry = telemetry(VFR_HUD_V2_TRUNCATED);
+
+        assert_close(telemetry.airspeed, 12.5);
+        assert_close(telemetry.vertical_speed, -1.25);
+        // The zero bytes left off the end
+        assert_close(telemetry.heading, 0.0);
+    }
+
+    #[test]
+    fn signed() {
+        let [frame] = frames(ATTITUDE_V2_SIGNED).try_into().unwrap();
+
+        assert_eq!(frame.len, ATTITUDE_V2_SIGNED.len());
+        assert_eq!(frame.payload.len(), 28);
+        assert!(matches!(
+            Message::decode(&frame),
+            Some(Message::Attitude(_))
+        ));
+    }
+
+    #[test]
+    fn bad_checksum() {
+        let mut corrupt = VFR_HUD_V1.to_vec();
+        corrupt[10] ^= 0x01;
+
+        assert!(frames(&corrupt).is_empty());
+
+        // The next good frame still comes through
+        corrupt.extend_from_slice(ATTITUDE_V2);
+        let [frame] = frames(&corrupt).try_into().unwrap();
+        assert_eq!(frame.message_id, 30);
+    }
+
+    #[test]
+    fn resync() {
+        // A false magic byte claiming a payload too long for a heartbeat, then the header of
+        // a heartbeat that takes the start of the real one as its payload and checksum
+        let mut bytes = vec![0x00, 0xFE, 0x30, 0x00, 0x01, 0x01, 0x00, 0x13];
+        bytes.extend_from_slice(&HEARTBEAT_V1[..6]);
+        bytes.extend_from_slice(HEARTBEAT_V1);
+        bytes.extend_from_slice(&[0x55, 0xAA]);
+        bytes.extend_from_slice(GLOBAL_POSITION_INT_V2);
+
+        let ids: Vec<u32> = frames(&bytes)
+            .iter()
+            .map(|frame| frame.message_id)
+            .collect();
+        assert_eq!(ids, [0, 33]);
+    }
+
+    #[test]
+    fn split_across_pushes() {
+        let mut decoder = Decoder::default();
+        let (head, tail) = GLOBAL_POSITION_INT_V2.split_at(15);
+
+        decoder.push(head);
+        assert!(decoder.next_frame().is_none());
+
+        decoder.push(tail);
+        assert_eq!(decoder.next_frame().unwrap().message_id, 33);
+    }
+}
@@ -0,0 +1,449 @@
+//! Orientations as rotations, to combine and correct them without the pitfalls of Euler angles
+//!
+//! Attitudes follow the aircraft convention: heading, pitch and roll are applied in that order
+//! (intrinsic Z-Y'-X''), in a north-east-down world frame. Phones report their orientation in
+//! the W3C convention instead, see [`DeviceOrientation`].
+
+use std::ops::Mul;
+
+use serde::{Deserialize, Serialize};
+
+/// How close to ±90° pitch has to be to be treated as gimbal lock
+const GIMBAL_LOCK_EPSILON: f64 = 1e-9;
+
+/// Aircraft attitude in degrees, see the [module documentation](self)
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Attitude {
+    /// Clockwise from north, 0..360
+    pub heading: f32,
+    /// Nose up positive, -90..90
+    pub pitch: f32,
+    /// Right wing down positive, -180..180
+    pub roll: f32,
+}
+
+impl Attitude {
+    pub fn new(heading: f32, pitch: f32, roll: f32) -> Self {
+        Self {
+            heading,
+            pitch,
+            roll,
+        }
+    }
+
+    /// Rotation from the body frame to the world frame
+    pub fn quaternion(self) -> Quaternion {
+        Quaternion::from_axis_angle([0.0, 0.0, 1.0], f64::from(self.heading).to_radians())
+            * Quaternion::from_axis_angle([0.0, 1.0, 0.0], f64::from(self.pitch).to_radians())
+            * Quaternion::from_axis_angle([1.0, 0.0, 0.0], f64::from(self.roll).to_radians())
+    }
+
+    /// The attitude of a body frame rotated into the world frame by `rotation`
+    ///
+    /// Pointing straight up or down, heading and roll turn about the same axis. Roll is then
+    /// taken to be zero, and the heading carries the whole rotation.
+    pub fn from_quaternion(rotation: Quaternion) -> Self {
+        let Quaternion { w, x, y, z } = rotation.normalized();
+        let sin_pitch = (2.0 * (w * y - z * x)).clamp(-1.0, 1.0);
+
+        let (heading, pitch, roll) = if sin_pitch.abs() >= 1.0 - GIMBAL_LOCK_EPSILON {
+            (
+                2.0 * f64::atan2(-sin_pitch.signum() * x, w),
+                sin_pitch.signum() * std::f64::consts::FRAC_PI_2,
+                0.0,
+            )
+        } else {
+            (
+                f64::atan2(2.0 * (w * z + x * y), 1.0 - 2.0 * (y * y + z * z)),
+                sin_pitch.asin(),
+                f64::atan2(2.0 * (w * x + y * z), 1.0 - 2.0 * (x * x + y * y)),
+            )
+        };
+
+        Self {
+            heading: heading.to_degrees().rem_euclid(360.0) as f32,
+            pitch: pitch.to_degrees() as f32,
+            roll: roll.to_degrees() as f32,
+        }
+    }
+}
+
+/// Orientation of a device as reported by the W3C `deviceorientation` event, in degrees
+///
+/// The angles are intrinsic Z-X'-Y'' rotations of the device in an east-north-up frame, with
+/// alpha counter-clockwise. The device frame has x to the right of the screen, y to the top
+/// of the screen and z out of the screen.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct DeviceOrientation {
+    pub alpha: f32,
+    pub beta: f32,
+    pub gamma: f32,
+    #[serde(default)]
+    pub mounting: DeviceMounting,
+}
+
+/// How a device sits in the airframe
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeviceMounting {
+    /// Screen up, with the top of the screen towards the nose
+    #[default]
+    Flat,
+    /// Upright, with the screen facing the tail
+    Portrait,
+    /// On its side, with the screen facing the tail and the top of the screen to the left
+    Landscape,
+}
+
+impl DeviceMounting {
+    /// Rotation from the body frame to the device frame
+    fn quaternion(self) -> Quaternion {
+        // Where the front, right and bottom of the aircraft point in the device frame
+        let axes = match self {
+            DeviceMounting::Flat => [[0.0, 1.0, 0.0], [1.0, 0.0, 0.0], [0.0, 0.0, -1.0]],
+            DeviceMounting::Portrait => [[0.0, 0.0, -1.0], [1.0, 0.0, 0.0], [0.0, -1.0, 0.0]],
+            DeviceMounting::Landscape => [[0.0, 0.0, -1.0], [0.0, -1.0, 0.0], [-1.0, 0.0, 0.0]],
+        };
+
+        Quaternion::from_axes(axes)
+    }
+}
+
+impl DeviceOrientation {
+    /// Attitude of the aircraft the device is mounted in
+    pub fn attitude(self) -> Attitude {
+        // Swaps north and east and flips up, a half turn about the north-east diagonal
+        const ENU_TO_NED: Quaternion = Quaternion {
+            w: 0.0,
+            x: std::f64::consts::FRAC_1_SQRT_2,
+            y: std::f64::consts::FRAC_1_SQRT_2,
+            z: 0.0,
+        };
+
+        let device =
+            Quaternion::from_axis_angle([0.0, 0.0, 1.0], f64::from(self.alpha).to_radians())
+                * Quaternion::from_axis_angle([1.0, 0.0, 0.0], f64::from(self.beta).to_radians())
+                * Quaternion::from_axis_angle([0.0, 1.0, 0.0], f64::from(self.gamma).to_radians());
+
+        Attitude::from_quaternion(ENU_TO_NED * device * self.mounting.quaternion())
+    }
+}
+
+/// A rotation, as a unit quaternion
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Quaternion {
+    pub w: f64,
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+}
+
+impl Quaternion {
+    pub const IDENTITY: Self = Self {
+        w: 1.0,
+        x: 0.0,
+        y: 0.0,
+        z: 0.0,
+    };
+
+    /// Right-handed rotation of `angle` radians about the unit vector `axis`
+    pub fn from_axis_angle([x, y, z]: [f64; 3], angle: f64) -> Self {
+        let (sin, cos) = (angle / 2.0).sin_cos();
+
+        Self {
+            w: cos,
+            x: x * sin,
+            y: y * sin,
+            z: z * sin,
+        }
+    }
+
+    /// Rotation taking the x, y and z axes to the orthonormal, right-handed `axes`
+    pub fn from_axes([x_axis, y_axis, z_axis]: [[f64; 3]; 3]) -> Self {
+        // Rotation matrix m[row][column], with the axes as columns
+        let m = |row: usize, column: usize| [x_axis, y_axis, z_axis][column][row];
+        let trace = m(0, 0) + m(1, 1) + m(2, 2);
+
+        // Divide by the largest component to stay accurate for half turns
+        if trace > 0.0 {
+            let s = (trace + 1.0).sqrt() * 2.0;
+            Self {
+                w: s / 4.0,
+                x: (m(2, 1) - m(1, 2)) / s,
+                y: (m(0, 2) - m(2, 0)) / s,
+                z: (m(1, 0) - m(0, 1)) / s,
+            }
+        } else if m(0, 0) > m(1, 1) && m(0, 0) > m(2, 2) {
+            let s = (1.0 + m(0, 0) - m(1, 1) - m(2, 2)).sqrt() * 2.0;
+            Self {
+                w: (m(2, 1) - m(1, 2)) / s,
+                x: s / 4.0,
+                y: (m(0, 1) + m(1, 0)) / s,
+                z: (m(0, 2) + m(2, 0)) / s,
+            }
+        } else if m(1, 1) > m(2, 2) {
+            let s = (1.0 + m(1, 1) - m(0, 0) - m(2, 2)).sqrt() * 2.0;
+            Self {
+                w: (m(0, 2) - m(2, 0)) / s,
+                x: (m(0, 1) + m(1, 0)) / s,
+                y: s / 4.0,
+                z: (m(1, 2) + m(2, 1)) / s,
+            }
+        } else {
+            let s = (1.0 + m(2, 2) - m(0, 0) - m(1, 1)).sqrt() * 2.0;
+            Self {
+                w: (m(1, 0) - m(0, 1)) / s,
+                x: (m(0, 2) + m(2, 0)) / s,
+                y: (m(1, 2) + m(2, 1)) / s,
+                z: s / 4.0,
+            }
+        }
+    }
+
+    /// Rotate the vector `v`
+    pub fn rotate(self, v: [f64; 3]) -> [f64; 3] {
+        let Quaternion { x, y, z, .. } =
+            self * Quaternion {
+                w: 0.0,
+                x: v[0],
+                y: v[1],
+                z: v[2],
+            } * self.inverse();
+
+        [x, y, z]
+    }
+
+    /// The opposite rotation
+    pub fn inverse(self) -> Self {
+        Self {
+            w: self.w,
+            x: -self.x,
+            y: -self.y,
+            z: -self.z,
+        }
+    }
+
+    /// Undo the drift from unit length that repeated products accumulate
+    pub fn normalized(self) -> Self {
+        let norm = (self.w * self.w + self.x * self.x + self.y * self.y + self.z * self.z).sqrt();
+
+        Self {
+            w: self.w / norm,
+            x: self.x / norm,
+            y: self.y / norm,
+            z: self.z / norm,
+        }
+    }
+}
+
+/// `a * b` rotates by `b` first, then by `a`
+impl Mul for Quaternion {
+    type Output = Quaternion;
+
+    fn mul(self, rhs: Self) -> Self::Output {
+        let Quaternion { w, x, y, z } = self;
+
+        Quaternion {
+            w: w * rhs.w - x * rhs.x - y * rhs.y - z * rhs.z,
+            x: w * rhs.x + x * rhs.w + y * rhs.z - z * rhs.y,
+            y: w * rhs.y - x * rhs.z + y * rhs.w + z * rhs.x,
+            z: w * rhs.z + x * rhs.y - y * rhs.x + z * rhs.w,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_attitude_eq(actual: Attitude, expected: Attitude) {
+        let angle_error = |a: f32, b: f32| ((a - b + 180.0).rem_euclid(360.0) - 180.0).abs();
+
+        assert!(
+            angle_error(actual.heading, expected.heading) < 1e-3
+                && (actual.pitch - expected.pitch).abs() < 1e-3
+                && angle_error(actual.roll, expected.roll) < 1e-3,
+            "{actual:?} != {expected:?}"
+        );
+    }
+
+    /// Whether two quaternions are the same rotation, `q` and `-q` included
+    fn same_rotation(a: Quaternion, b: Quaternion) -> bool {
+        let dot = a.w * b.w + a.x * b.x + a.y * b.y + a.z * b.z;
+
+        (dot.abs() - 1.0).abs() < 1e-9
+    }
+
+    fn device(alpha: f32, beta: f32, gamma: f32, mounting: DeviceMounting) -> Attitude {
+        DeviceOrientation {
+            alpha,
+            beta,
+            gamma,
+            mounting,
+        }
+        .attitude()
+    }
+
+    #[test]
+    fn euler_round_trip() {
+        for heading in [0.0, 45.0, 135.0, 270.0, 359.0] {
+            for pitch in [-89.0, -30.0, 0.0, 10.0, 89.0] {
+                for roll in [-179.0, -60.0, 0.0, 20.0, 120.0] {
+                    let attitude = Attitude::new(heading, pitch, roll);
+
+                    assert_attitude_eq(Attitude::from_quaternion(attitude.quaternion()), attitude);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn aircraft_gimbal_lock() {
+        // Nose straight up or down, heading and roll are the same rotation
+        for pitch in [90.0, -90.0] {
+            for (heading, roll) in [(0.0, 0.0), (30.0, 0.0), (30.0, 40.0), (300.0, -170.0)] {
+                let rotation = Attitude::new(heading, pitch, roll).quaternion();
+                let attitude = Attitude::from_quaternion(rotation);
+
+                assert!(attitude.heading.is_finite(), "{attitude:?}");
+                assert_eq!(attitude.pitch, pitch);
+                assert_eq!(attitude.roll, 0.0);
+                assert!(
+                    same_rotation(attitude.quaternion(), rotation),
+                    "{attitude:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn from_axes() {
+        let rotation = Attitude::new(30.0, 20.0, -50.0).quaternion();
+        let axes =
+            [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]].map(|axis| rotation.rotate(axis));
+
+        assert!(same_rotation(Quaternion::from_axes(axes), rotation));
+
+        // Half turns, where the quaternion has no real part
+        for axes in [
+            [[1.0, 0.0, 0.0], [0.0, -1.0, 0.0], [0.0, 0.0, -1.0]],
+            [[-1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, -1.0]],
+            [[-1.0, 0.0, 0.0], [0.0, -1.0, 0.0], [0.0, 0.0, 1.0]],
+        ] {
+            let rotation = Quaternion::from_axes(axes);
+
+            for (axis, expected) in [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]]
+                .iter()
+                .zip(axes)
+            {
+                let rotated = rotation.rotate(*axis);
+                assert!(
+                    rotated
+                        .iter()
+                        .zip(expected)
+                        .all(|(a, b)| (a - b).abs() < 1e-9),
+                    "{rotated:?} != {expected:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn flat_small_angles() {
+        // Away from the poles, a flat phone matches the naive mapping except for alpha's direction
+        assert_attitude_eq(
+            device(0.0, 0.0, 0.0, DeviceMounting::Flat),
+            Attitude::new(0.0, 0.0, 0.0),
+        );
+        assert_attitude_eq(
+            device(30.0, 0.0, 0.0, DeviceMounting::Flat),
+            Attitude::new(330.0, 0.0, 0.0),
+        );
+        assert_attitude_eq(
+            device(0.0, 10.0, 0.0, DeviceMounting::Flat),
+            Attitude::new(0.0, 10.0, 0.0),
+        );
+        assert_attitude_eq(
+            device(0.0, 0.0, -20.0, DeviceMounting::Flat),
+            Attitude::new(0.0, 0.0, -20.0),
+        );
+    }
+
+    #[test]
+    fn flat_large_angles() {
+        // Tilting past 45° in both axes is where the naive mapping goes wrong: the W3C
+        // convention applies gamma last, about the already pitched device
+        let expected = Attitude::from_quaternion(
+            Quaternion::from_axis_angle([0.0, 1.0, 0.0], 60f64.to_radians())
+                * Quaternion::from_axis_angle([1.0, 0.0, 0.0], 50f64.to_radians()),
+        );
+        assert_attitude_eq(device(0.0, 60.0, 50.0, DeviceMounting::Flat), expected);
+        assert_attitude_eq(expected, Attitude::new(0.0, 60.0, 50.0));
+
+        // Upside down, which W3C reports as beta beyond 90
+        assert_attitude_eq(
+            device(0.0, 180.0, 0.0, DeviceMounting::Flat),
+            Attitude::new(180.0, 0.0, 180.0),
+        );
+    }
+
+    #[test]
+    fn device_gimbal_lock() {
+        // A phone standing upright has beta 90, where alpha and gamma turn about the same axis.
+        // Every combination of them with the same sum is the same orientation.
+        let upright = device(20.0, 90.0, 0.0, DeviceMounting::Portrait);
+        assert_attitude_eq(upright, Attitude::new(340.0, 0.0, 0.0));
+        assert_attitude_eq(device(50.0, 90.0, -30.0, DeviceMounting::Portrait), upright);
+        assert_attitude_eq(device(-10.0, 90.0, 30.0, DeviceMounting::Portrait), upright);
+
+        // Mounted flat, the same orientation is the nose pointing straight up
+        let nose_up = device(20.0, 90.0, 0.0, DeviceMounting::Flat);
+        assert_eq!(nose_up.pitch, 90.0);
+        assert_eq!(nose_up.roll, 0.0);
+        assert_attitude_eq(device(50.0, 90.0, -30.0, DeviceMounting::Flat), nose_up);
+    }
+
+    #[test]
+    fn portrait() {
+        // Upright, facing the pilot: alpha is still the heading
+        assert_attitude_eq(
+            device(0.0, 90.0, 0.0, DeviceMounting::Portrait),
+            Attitude::new(0.0, 0.0, 0.0),
+        );
+        assert_attitude_eq(
+            device(90.0, 90.0, 0.0, DeviceMounting::Portrait),
+            Attitude::new(270.0, 0.0, 0.0),
+        );
+
+        // Leaning back, as the screen tilts up when the nose pitches up
+        assert_attitude_eq(
+            device(0.0, 110.0, 0.0, DeviceMounting::Portrait),
+            Attitude::new(0.0, 20.0, 0.0),
+        );
+
+        // Lying flat with the screen up, the back of the phone and the nose point down
+        let nose_down = device(0.0, 0.0, 0.0, DeviceMounting::Portrait);
+        assert_eq!(nose_down.pitch, -90.0);
+        assert_eq!(nose_down.roll, 0.0);
+    }
+
+    #[test]
+    fn landscape() {
+        // Top of the screen to the left and the screen facing west, so the nose points east
+        assert_attitude_eq(
+            device(0.0, 0.0, -90.0, DeviceMounting::Landscape),
+            Attitude::new(90.0, 0.0, 0.0),
+        );
+        assert_attitude_eq(
+            device(90.0, 0.0, -90.0, DeviceMounting::Landscape),
+            Attitude::new(0.0, 0.0, 0.0),
+        );
+
+        // Leaning back, as the screen tilts up when the nose pitches up
+        assert_attitude_eq(
+            device(0.0, 0.0, -110.0, DeviceMounting::Landscape),
+            Attitude::new(90.0, 20.0, 0.0),
+        );
+    }
+}
@@ -0,0 +1,396 @@
+//! Recording of received telemetry to disk, and replaying it back into the vehicle registry
+//!
+//! Recordings are stored as JSON lines, one [`Sample`] per line.
+
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{self, BufRead, BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use parking_lot::Mutex;
+use tracing::{debug, warn};
+
+use crate::{
+    connection::{Connection, ConnectionId},
+    telemetry::Telemetry,
+    vehicle::{VehicleId, Vehicles},
+};
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Sample {
+    /// Seconds since the start of the recording
+    pub time: f64,
+    pub vehicle: VehicleId,
+    /// The message as received, recordings from before the telemetry schema stored `attitude`
+    #[serde(alias = "attitude")]
+    pub telemetry: Telemetry,
+}
+
+/// Shared handle to the (optional) active recording, cloned into every ingest thread
+#[derive(Clone, Default)]
+pub struct Recorder(Arc<Mutex<Option<Recording>>>);
+
+struct Recording {
+    path: PathBuf,
+    file: BufWriter<File>,
+    start: Instant,
+}
+
+impl Recorder {
+    /// Start recording to `path`, replacing any active recording
+    pub fn start(&self, path: impl Into<PathBuf>) -> io::Result<()> {
+        let path = path.into();
+        let file = BufWriter::new(File::create(&path)?);
+        debug!(path = %path.display(), "recording started");
+
+        *self.0.lock() = Some(Recording {
+            path,
+            file,
+            start: Instant::now(),
+        });
+
+        Ok(())
+    }
+
+    pub fn stop(&self) {
+        if let Some(mut recording) = self.0.lock().take() {
+            if let Err(error) = recording.file.flush() {
+                warn!(%error, "failed to flush recording");
+            }
+            debug!(path = %recording.path.display(), "recording stopped");
+        }
+    }
+
+    /// Path of the active recording
+    pub fn path(&self) -> Option<PathBuf> {
+        self.0
+            .lock()
+            .as_ref()
+            .map(|recording| recording.path.clone())
+    }
+
+    /// Append a sample to the active recording, if any
+    pub fn record(&self, vehicle: &VehicleId, telemetry: &Telemetry) {
+        let mut recording = self.0.lock();
+        let Some(recording) = recording.as_mut() else {
+            return;
+        };
+
+        let sample = Sample {
+            time: recording.start.elapsed().as_secs_f64(),
+            vehicle: vehicle.clone(),
+            telemetry: telemetry.clone(),
+        };
+
+        let result = serde_json::to_writer(&mut recording.file, &sample)
+            .map_err(io::Error::from)
+            .and_then(|()| recording.file.write_all(b"\n"));
+
+        if let Err(error) = result {
+            warn!(%error, path = %recording.path.display(), "failed to write recording");
+        }
+    }
+}
+
+/// A default file name for a new recording, unique per second
+pub fn default_recording_path() -> PathBuf {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+
+    PathBuf::from(format!("flock-{timestamp}.jsonl"))
+}
+
+/// Number of samples between two [`Checkpoint`]s of a replay
+const CHECKPOINT_INTERVAL: usize = 1024;
+
+/// Latest state of every vehicle at some point of a recording, where seeking starts from
+type Checkpoint = HashMap<VehicleId, (f64, Telemetry)>;
+
+/// Playback of a recording into the vehicle registry, driven by the UI every frame
+pub struct Replay {
+    path: PathBuf,
+    samples: Vec<Sample>,
+    /// Every vehicle in the recording
+    vehicles: Vec<VehicleId>,
+    /// The state before every [`CHECKPOINT_INTERVAL`]th sample, so seeking does not replay the
+    /// whole recording up to the position
+    checkpoints: Vec<Checkpoint>,
+    /// Lines of the recording that were not a valid sample, and skipped
+    skipped: usize,
+    /// The connection every vehicle in the recording is fed through
+    connection: ConnectionId,
+    /// Position in the recording, in seconds
+    position: f64,
+    /// Number of samples already applied to the vehicles, up to the position
+    applied: usize,
+    pub speed: f64,
+    pub paused: bool,
+}
+
+impl Replay {
+    /// Open the recording at `path`, skipping the lines that are not a valid sample, like the
+    /// last one when Flock crashed in the middle of writing it
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref();
+        let replay = Self::read(path, BufReader::new(File::open(path)?))?;
+
+        if replay.skipped > 0 {
+            warn!(
+                path = %path.display(),
+                skipped = replay.skipped,
+                "skipped invalid lines in replay"
+            );
+        }
+        debug!(path = %path.display(), samples = replay.samples.len(), "replay opened");
+
+        Ok(replay)
+    }
+
+    fn read(path: &Path, reader: impl BufRead) -> io::Result<Self> {
+        let mut samples = Vec::new();
+        let mut skipped = 0;
+        for line in reader.split(b'\n') {
+            let line = line?;
+            if line.trim_ascii().is_empty() {
+                continue;
+            }
+
+            match serde_json::from_slice::<Sample>(&line) {
+                Ok(sample) => samples.push(sample),
+                Err(error) => {
+                    debug!(%error, path = %path.display(), "invalid line in replay");
+                    skipped += 1;
+                }
+            }
+        }
+        samples.sort_by(|a, b| a.time.total_cmp(&b.time));
+
+        let mut vehicles = samples
+            .iter()
+            .map(|sample| sample.vehicle.clone())
+            .collect::<Vec<_>>();
+        vehicles.sort();
+        vehicles.dedup();
+
+        let mut checkpoints = vec![Checkpoint::new()];
+        for chunk in samples.chunks_exact(CHECKPOINT_INTERVAL) {
+            let mut checkpoint = checkpoints[checkpoints.len() - 1].clone();
+            for sample in chunk {
+                let (time, telemetry) = checkpoint.entry(sample.vehicle.clone()).or_default();
+                *time = sample.time;
+                telemetry.merge(&sample.telemetry);
+            }
+            checkpoints.push(checkpoint);
+        }
+
+        Ok(Self {
+            path: path.to_owned(),
+            samples,
+            vehicles,
+            checkpoints,
+            skipped,
+            connection: ConnectionId::next(),
+            position: 0.0,
+            applied: 0,
+            speed: 1.0,
+            paused: false,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn duration(&self) -> f64 {
+        self.samples.last().map_or(0.0, |sample| sample.time)
+    }
+
+    pub fn position(&self) -> f64 {
+        self.position
+    }
+
+    pub fn finished(&self) -> bool {
+        self.position >= self.duration()
+    }
+
+    /// Number of lines of the recording that were not a valid sample
+    pub fn skipped(&self) -> usize {
+        self.skipped
+    }
+
+    /// Mark every vehicle in the recording as (dis)connected in the registry
+    pub fn set_connected(&self, vehicles: &mut Vehicles, connected: bool) {
+        for id in &self.vehicles {
+            let vehicle = vehicles.get_or_insert(id);
+
+            if connected {
+                vehicle.connections.insert(
+                    self.connection,
+                    Connection::new("replay", self.path.display().to_string(), Instant::now()),
+                );
+            } else {
+                vehicle.connections.remove(&self.connection);
+            }
+        }
+    }
+
+    /// Advance playback by a frame's worth of wall clock time
+    pub fn advance(&mut self, elapsed: Duration, vehicles: &mut Vehicles) {
+        if self.paused || self.finished() {
+            return;
+        }
+
+        self.advance_to(self.position + elapsed.as_secs_f64() * self.speed, vehicles);
+    }
+
+    /// Play the recording up to `position`, in seconds, ignoring the speed and pause state
+    pub fn advance_to(&mut self, position: f64, vehicles: &mut Vehicles) {
+        self.position = position.min(self.duration());
+
+        let start = self.applied;
+        let end = self
+            .samples
+            .partition_point(|sample| sample.time <= self.position)
+            .max(start);
+        self.applied = end;
+
+        let now = Instant::now();
+        for sample in &self.samples[start..end] {
+            let time = self.instant_of(sample.time, now);
+            let vehicle = vehicles.get_or_insert(&sample.vehicle);
+
+            if let Some(connection) = vehicle.connections.get_mut(&self.connection) {
+                connection.received(time, 0);
+            }
+            vehicle.update(&sample.telemetry, time);
+        }
+    }
+
+    /// Jump to `position`, restoring the latest state of every vehicle at that point
+    ///
+    /// The state is restored from the last checkpoint before `position`, so the history of the
+    /// vehicles only goes back to there.
+    pub fn seek(&mut self, position: f64, vehicles: &mut Vehicles) {
+        self.position = position.clamp(0.0, self.duration());
+        self.applied = self
+            .samples
+            .partition_point(|sample| sample.time <= self.position);
+
+        let now = Instant::now();
+        for id in &self.vehicles {
+            vehicles.get_or_insert(id).reset();
+        }
+
+        let checkpoint = self.applied / CHECKPOINT_INTERVAL;
+        for (id, (time, telemetry)) in &self.checkpoints[checkpoint] {
+            vehicles
+                .get_or_insert(id)
+                .update(telemetry, self.instant_of(*time, now));
+        }
+        for sample in &self.samples[checkpoint * CHECKPOINT_INTERVAL..self.applied] {
+            vehicles
+                .get_or_insert(&sample.vehicle)
+                .update(&sample.telemetry, self.instant_of(sample.time, now));
+        }
+    }
+
+    /// Recording time of the latest sample of `id` up to the current position
+    pub fn last_sample_time(&self, id: &VehicleId) -> Option<f64> {
+        let end = self
+            .samples
+            .partition_point(|sample| sample.time <= self.position);
+
+        self.samples[..end]
+            .iter()
+            .rev()
+            .find(|sample| sample.vehicle == *id)
+            .map(|sample| sample.time)
+    }
+
+    /// When a sample at `time` would have been received, had playback run at the current speed
+    /// until `now`
+    fn instant_of(&self, time: f64, now: Instant) -> Instant {
+        let ago = (self.position - time).max(0.0) / self.speed;
+
+        now.checked_sub(Duration::from_secs_f64(ago)).unwrap_or(now)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::calibration::Calibrations;
+
+    fn replay(recording: &str) -> (Replay, Vehicles) {
+        let replay = Replay::read(Path::new("test.jsonl"), recording.as_bytes()).unwrap();
+        let (vehicles, _) = Vehicles::new(Calibrations::default());
+
+        (replay, vehicles)
+    }
+
+    #[test]
+    fn skips_invalid_lines() {
+        let (replay, _) = replay(concat!(
+            "{\"time\": 0.0, \"vehicle\": \"a\", \"telemetry\": {\"heading\": 1.0}}\n",
+            "\n",
+            "not a sample\n",
+            "{\"time\": 1.0, \"vehicle\": \"a\", \"telemetry\": {\"heading\": 2.0}}\n",
+            "{\"time\": 2.0, \"vehicle\": \"a\", \"tele",
+        ));
+
+        assert_eq!(replay.samples.len(), 2);
+        assert_eq!(replay.skipped(), 2);
+        assert_eq!(replay.duration(), 1.0);
+    }
+
+    #[test]
+    fn seek_to_start() {
+        let (mut replay, mut vehicles) = replay(concat!(
+            "{\"time\": 0.0, \"vehicle\": \"a\", \"telemetry\": {\"heading\": 1.0}}\n",
+            "{\"time\": 1.0, \"vehicle\": \"a\", \"telemetry\": {\"heading\": 2.0}}\n",
+        ));
+
+        replay.seek(0.0, &mut vehicles);
+        replay.advance_to(0.5, &mut vehicles);
+        assert_eq!(vehicles[&VehicleId::new("a")].history.len(), 1);
+
+        replay.advance_to(1.0, &mut vehicles);
+        let vehicle = &vehicles[&VehicleId::new("a")];
+        assert_eq!(vehicle.history.len(), 2);
+        assert_eq!(vehicle.telemetry.heading, Some(2.0));
+    }
+
+    #[test]
+    fn seek_from_checkpoint() {
+        let recording = (0..CHECKPOINT_INTERVAL * 2 + 10)
+            .map(|index| {
+                let field = if index % 2 == 0 { "pitch" } else { "roll" };
+                format!(
+                    "{{\"time\": {index}, \"vehicle\": \"a\", \"telemetry\": {{\"{field}\": {index}}}}}\n"
+                )
+            })
+            .collect::<String>();
+        let (mut replay, mut vehicles) = replay(&recording);
+        assert_eq!(replay.checkpoints.len(), 3);
+
+        let position = (CHECKPOINT_INTERVAL * 2 + 4) as f64;
+        replay.seek(position, &mut vehicles);
+        let vehicle = &vehicles[&VehicleId::new("a")];
+        assert_eq!(vehicle.telemetry.pitch, Some(position as f32));
+        assert_eq!(vehicle.telemetry.roll, Some(position as f32 - 1.0));
+        assert_eq!(vehicle.history.len(), 6);
+
+        // Back to before the first checkpoint, forgetting everything after it
+        replay.seek(1.0, &mut vehicles);
+        let vehicle = &vehicles[&VehicleId::new("a")];
+        assert_eq!(vehicle.telemetry.pitch, Some(0.0));
+        assert_eq!(vehicle.telemetry.roll, Some(1.0));
+        assert_eq!(vehicle.history.len(), 2);
+    }
+}
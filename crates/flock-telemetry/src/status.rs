@@ -0,0 +1,56 @@
+use std::{collections::BTreeMap, fmt::Display, sync::Arc};
+
+use parking_lot::Mutex;
+
+/// Health of the ingest listeners, shown in the status bar
+pub type Status = Arc<Mutex<IngestStatus>>;
+
+#[derive(Debug, Default)]
+pub struct IngestStatus {
+    /// Every enabled listener by the name shown in the status bar
+    pub listeners: BTreeMap<&'static str, ListenerStatus>,
+    /// URL phones can open to get the sensor page
+    pub phone_page: Option<String>,
+}
+
+#[derive(Debug, Default)]
+pub struct ListenerStatus {
+    /// Address the listener is bound to, once bound
+    pub address: Option<String>,
+    /// Why binding the listener failed, cleared once it succeeds
+    pub bind_error: Option<String>,
+    /// Why the listener can not start at all, until its configuration is fixed
+    pub config_error: Option<String>,
+    /// Connections that failed before any telemetry could be received
+    pub handshake_errors: usize,
+    pub last_handshake_error: Option<String>,
+}
+
+impl ListenerStatus {
+    pub fn bound(&mut self, address: impl Display) {
+        self.address = Some(address.to_string());
+        self.bind_error = None;
+    }
+
+    pub fn bind_failed(&mut self, error: impl Display) {
+        self.address = None;
+        self.bind_error = Some(error.to_string());
+    }
+
+    pub fn config_invalid(&mut self, error: impl Display) {
+        self.address = None;
+        self.config_error = Some(error.to_string());
+    }
+
+    pub fn handshake_failed(&mut self, error: impl Display) {
+        self.handshake_errors += 1;
+        self.last_handshake_error = Some(error.to_string());
+    }
+}
+
+/// A telemetry message that could not be decoded
+#[derive(Debug, Clone)]
+pub struct ParseError {
+    pub error: String,
+    pub payload: String,
+}
@@ -0,0 +1,518 @@
+//! The telemetry model shared by every ingest source, the recorder and the widgets
+//!
+//! Every field is optional: a message only carries what its sender knows, and is merged into
+//! the latest known state of the vehicle. The original phone page sends just
+//! `{"heading": .., "pitch": .., "roll": ..}`, which is a valid version 1 message.
+
+use serde::{Deserialize, Serialize};
+
+use crate::orientation::DeviceOrientation;
+
+/// Newest schema version this build understands
+pub const SCHEMA_VERSION: u32 = 1;
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Telemetry {
+    /// Schema version the sender speaks, assumed to be 1 when missing
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<u32>,
+
+    /// degrees, clockwise from north
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub heading: Option<f32>,
+    /// degrees, nose up positive
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pitch: Option<f32>,
+    /// degrees, right wing down positive
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub roll: Option<f32>,
+    /// Raw orientation of a phone, converted to heading, pitch and roll when decoding
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub device_orientation: Option<DeviceOrientation>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub position: Option<Position>,
+    /// m above mean sea level
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub altitude: Option<f32>,
+    /// m above the home position
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub relative_altitude: Option<f32>,
+
+    /// m/s
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub airspeed: Option<f32>,
+    /// m/s
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub groundspeed: Option<f32>,
+    /// m/s, positive up
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub vertical_speed: Option<f32>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub battery: Option<Battery>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub gps: Option<Gps>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub flight_mode: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub armed: Option<bool>,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Position {
+    /// degrees, WGS84
+    pub latitude: f64,
+    /// degrees, WGS84
+    pub longitude: f64,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Battery {
+    /// V
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub voltage: Option<f32>,
+    /// A
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub current: Option<f32>,
+    /// percent
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub remaining: Option<f32>,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Gps {
+    pub fix: GpsFix,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub satellites: Option<u8>,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GpsFix {
+    #[default]
+    None,
+    #[serde(rename = "2d")]
+    Fix2D,
+    #[serde(rename = "3d")]
+    Fix3D,
+    Dgps,
+    RtkFloat,
+    RtkFixed,
+}
+
+/// A numeric telemetry field, for plotting and export
+pub struct NumericField {
+    pub name: &'static str,
+    pub unit: &'static str,
+    pub value: fn(&Telemetry) -> Option<f64>,
+}
+
+/// Every numeric field of [`Telemetry`]
+pub const NUMERIC_FIELDS: &[NumericField] = &[
+    NumericField {
+        name: "heading",
+        unit: "°",
+        value: |telemetry| telemetry.heading.map(f64::from),
+    },
+    NumericField {
+        name: "pitch",
+        unit: "°",
+        value: |telemetry| telemetry.pitch.map(f64::from),
+    },
+    NumericField {
+        name: "roll",
+        unit: "°",
+        value: |telemetry| telemetry.roll.map(f64::from),
+    },
+    NumericField {
+        name: "latitude",
+        unit: "°",
+        value: |telemetry| telemetry.position.map(|position| position.latitude),
+    },
+    NumericField {
+        name: "longitude",
+        unit: "°",
+        value: |telemetry| telemetry.position.map(|position| position.longitude),
+    },
+    NumericField {
+        name: "altitude",
+        unit: "m",
+        value: |telemetry| telemetry.altitude.map(f64::from),
+    },
+    NumericField {
+        name: "relative_altitude",
+        unit: "m",
+        value: |telemetry| telemetry.relative_altitude.map(f64::from),
+    },
+    NumericField {
+        name: "airspeed",
+        unit: "m/s",
+        value: |telemetry| telemetry.airspeed.map(f64::from),
+    },
+    NumericField {
+        name: "groundspeed",
+        unit: "m/s",
+        value: |telemetry| telemetry.groundspeed.map(f64::from),
+    },
+    NumericField {
+        name: "vertical_speed",
+        unit: "m/s",
+        value: |telemetry| telemetry.vertical_speed.map(f64::from),
+    },
+    NumericField {
+        name: "battery_voltage",
+        unit: "V",
+        value: |telemetry| telemetry.battery?.voltage.map(f64::from),
+    },
+    NumericField {
+        name: "battery_current",
+        unit: "A",
+        value: |telemetry| telemetry.battery?.current.map(f64::from),
+    },
+    NumericField {
+        name: "battery_remaining",
+        unit: "%",
+        value: |telemetry| telemetry.battery?.remaining.map(f64::from),
+    },
+    NumericField {
+        name: "satellites",
+        unit: "",
+        value: |telemetry| telemetry.gps?.satellites.map(f64::from),
+    },
+];
+
+/// Encoding of a binary telemetry message, declared by the first byte of the message
+///
+/// The rest of the message is the same model as the JSON messages, encoded as a map.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryEncoding {
+    Cbor,
+    MessagePack,
+}
+
+impl BinaryEncoding {
+    pub fn from_tag(tag: u8) -> Option<Self> {
+        match tag {
+            0x01 => Some(BinaryEncoding::Cbor),
+            0x02 => Some(BinaryEncoding::MessagePack),
+            _ => None,
+        }
+    }
+
+    pub fn tag(self) -> u8 {
+        match self {
+            BinaryEncoding::Cbor => 0x01,
+            BinaryEncoding::MessagePack => 0x02,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum TelemetryError {
+    Json(serde_json::Error),
+    Cbor(ciborium::de::Error<std::io::Error>),
+    MessagePack(rmp_serde::decode::Error),
+    /// A binary message starting with a tag that is not a [`BinaryEncoding`], if any
+    UnknownEncoding(Option<u8>),
+    UnsupportedVersion(u32),
+}
+
+impl std::fmt::Display for TelemetryError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TelemetryError::Json(error) => error.fmt(f),
+            TelemetryError::Cbor(error) => write!(f, "invalid CBOR: {error}"),
+            TelemetryError::MessagePack(error) => write!(f, "invalid MessagePack: {error}"),
+            TelemetryError::UnknownEncoding(Some(tag)) => {
+                write!(f, "unknown binary encoding tag {tag:#04x}")
+            }
+            TelemetryError::UnknownEncoding(None) => f.write_str("empty binary message"),
+            TelemetryError::UnsupportedVersion(version) => write!(
+                f,
+                "unsupported telemetry schema version {version}, newest supported is {SCHEMA_VERSION}"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for TelemetryError {}
+
+impl Telemetry {
+    /// Decode a JSON telemetry message
+    pub fn from_json(text: &str) -> Result<Self, TelemetryError> {
+        serde_json::from_str::<Telemetry>(text)
+            .map_err(TelemetryError::Json)?
+            .validate()
+    }
+
+    /// Decode a binary telemetry message, tagged with its [`BinaryEncoding`]
+    pub fn from_binary(bytes: &[u8]) -> Result<Self, TelemetryError> {
+        let (&tag, message) = bytes
+            .split_first()
+            .ok_or(TelemetryError::UnknownEncoding(None))?;
+
+        let telemetry: Telemetry = match BinaryEncoding::from_tag(tag) {
+            Some(BinaryEncoding::Cbor) => {
+                ciborium::from_reader(message).map_err(TelemetryError::Cbor)?
+            }
+            Some(BinaryEncoding::MessagePack) => {
+                rmp_serde::from_slice(message).map_err(TelemetryError::MessagePack)?
+            }
+            None => return Err(TelemetryError::UnknownEncoding(Some(tag))),
+        };
+
+        telemetry.validate()
+    }
+
+    fn validate(mut self) -> Result<Self, TelemetryError> {
+        if let Some(version) = self.version.filter(|&version| version > SCHEMA_VERSION) {
+            return Err(TelemetryError::UnsupportedVersion(version));
+        }
+
+        // Explicit angles win, so a sender can override part of the conversion
+        if let Some(device) = self.device_orientation.take() {
+            let attitude = device.attitude();
+            self.heading = self.heading.or(Some(attitude.heading));
+            self.pitch = self.pitch.or(Some(attitude.pitch));
+            self.roll = self.roll.or(Some(attitude.roll));
+        }
+
+        Ok(self)
+    }
+
+    /// Apply a (partial) update, keeping every field the update does not carry, down to the
+    /// fields of the battery and GPS
+    pub fn merge(&mut self, update: &Telemetry) {
+        fn merge<T: Clone>(field: &mut Option<T>, update: &Option<T>) {
+            if update.is_some() {
+                field.clone_from(update);
+            }
+        }
+
+        merge(&mut self.version, &update.version);
+        merge(&mut self.heading, &update.heading);
+        merge(&mut self.pitch, &update.pitch);
+        merge(&mut self.roll, &update.roll);
+        merge(&mut self.position, &update.position);
+        merge(&mut self.altitude, &update.altitude);
+        merge(&mut self.relative_altitude, &update.relative_altitude);
+        merge(&mut self.airspeed, &update.airspeed);
+        merge(&mut self.groundspeed, &update.groundspeed);
+        merge(&mut self.vertical_speed, &update.vertical_speed);
+        if let Some(update) = &update.battery {
+            let battery = self.battery.get_or_insert_with(Battery::default);
+            merge(&mut battery.voltage, &update.voltage);
+            merge(&mut battery.current, &update.current);
+            merge(&mut battery.remaining, &update.remaining);
+        }
+        if let Some(update) = &update.gps {
+            let gps = self.gps.get_or_insert_with(Gps::default);
+            // Not optional, a missing fix can not be told apart from no fix
+            gps.fix = update.fix;
+            merge(&mut gps.satellites, &update.satellites);
+        }
+        merge(&mut self.flight_mode, &update.flight_mode);
+        merge(&mut self.armed, &update.armed);
+    }
+
+    /// Every field that is present, formatted for display
+    pub fn fields(&self) -> Vec<(&'static str, String)> {
+        let mut fields = Vec::new();
+        let mut push = |name, value: Option<String>| {
+            if let Some(value) = value {
+                fields.push((name, value));
+            }
+        };
+
+        push(
+            "Heading",
+            self.heading.map(|heading| format!("{heading:.1}°")),
+        );
+        push("Pitch", self.pitch.map(|pitch| format!("{pitch:.1}°")));
+        push("Roll", self.roll.map(|roll| format!("{roll:.1}°")));
+        push(
+            "Position",
+            self.position
+                .map(|position| format!("{:.6}, {:.6}", position.latitude, position.longitude)),
+        );
+        push(
+            "Altitude",
+            self.altitude.map(|altitude| format!("{altitude:.1} m")),
+        );
+        push(
+            "Relative altitude",
+            self.relative_altitude
+                .map(|altitude| format!("{altitude:.1} m")),
+        );
+        push(
+            "Airspeed",
+            self.airspeed.map(|speed| format!("{speed:.1} m/s")),
+        );
+        push(
+            "Groundspeed",
+            self.groundspeed.map(|speed| format!("{speed:.1} m/s")),
+        );
+        push(
+            "Vertical speed",
+            self.vertical_speed.map(|speed| format!("{speed:+.1} m/s")),
+        );
+        push(
+            "Battery",
+            self.battery.map(|battery| {
+                [
+                    battery.voltage.map(|voltage| format!("{voltage:.2} V")),
+                    battery.current.map(|current| format!("{current:.1} A")),
+                    battery
+                        .remaining
+                        .map(|remaining| format!("{remaining:.0}%")),
+                ]
+                .into_iter()
+                .flatten()
+                .collect::<Vec<_>>()
+                .join(", ")
+            }),
+        );
+        push(
+            "GPS",
+            self.gps.map(|gps| match gps.satellites {
+                Some(satellites) => format!("{:?}, {satellites} satellites", gps.fix),
+                None => format!("{:?}", gps.fix),
+            }),
+        );
+        push("Flight mode", self.flight_mode.clone());
+        push(
+            "Armed",
+            self.armed
+                .map(|armed| if armed { "armed" } else { "disarmed" }.to_owned()),
+        );
+
+        fields
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> Telemetry {
+        Telemetry {
+            heading: Some(271.5),
+            position: Some(Position {
+                latitude: 51.4485,
+                longitude: 5.4907,
+            }),
+            flight_mode: Some("AUTO".to_owned()),
+            armed: Some(true),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn merge_nested() {
+        let mut telemetry = Telemetry {
+            battery: Some(Battery {
+                voltage: Some(12.4),
+                current: Some(8.0),
+                remaining: None,
+            }),
+            gps: Some(Gps {
+                fix: GpsFix::Fix3D,
+                satellites: Some(9),
+            }),
+            ..Default::default()
+        };
+
+        telemetry.merge(&Telemetry {
+            battery: Some(Battery {
+                remaining: Some(80.0),
+                ..Default::default()
+            }),
+            gps: Some(Gps {
+                fix: GpsFix::Fix2D,
+                satellites: None,
+            }),
+            ..Default::default()
+        });
+
+        assert_eq!(
+            telemetry.battery,
+            Some(Battery {
+                voltage: Some(12.4),
+                current: Some(8.0),
+                remaining: Some(80.0),
+            })
+        );
+        assert_eq!(
+            telemetry.gps,
+            Some(Gps {
+                fix: GpsFix::Fix2D,
+                satellites: Some(9),
+            })
+        );
+    }
+
+    #[test]
+    fn cbor() {
+        let mut message = vec![BinaryEncoding::Cbor.tag()];
+        ciborium::into_writer(&sample(), &mut message).unwrap();
+
+        assert_eq!(Telemetry::from_binary(&message).unwrap(), sample());
+    }
+
+    #[test]
+    fn message_pack() {
+        let mut message = vec![BinaryEncoding::MessagePack.tag()];
+        message.extend(rmp_serde::to_vec_named(&sample()).unwrap());
+
+        assert_eq!(Telemetry::from_binary(&message).unwrap(), sample());
+    }
+
+    #[test]
+    fn unknown_encoding() {
+        assert!(matches!(
+            Telemetry::from_binary(b"{}"),
+            Err(TelemetryError::UnknownEncoding(Some(b'{')))
+        ));
+        assert!(matches!(
+            Telemetry::from_binary(&[]),
+            Err(TelemetryError::UnknownEncoding(None))
+        ));
+    }
+
+    #[test]
+    fn binary_version_is_checked() {
+        let mut message = vec![BinaryEncoding::Cbor.tag()];
+        let telemetry = Telemetry {
+            version: Some(SCHEMA_VERSION + 1),
+            ..sample()
+        };
+        ciborium::into_writer(&telemetry, &mut message).unwrap();
+
+        assert!(matches!(
+            Telemetry::from_binary(&message),
+            Err(TelemetryError::UnsupportedVersion(_))
+        ));
+    }
+
+    #[test]
+    fn device_orientation() {
+        // An upright phone facing the pilot, with its back to the east
+        let telemetry = Telemetry::from_json(
+            r#"{"device_orientation": {"alpha": 270, "beta": 90, "gamma": 0, "mounting": "portrait"}}"#,
+        )
+        .unwrap();
+
+        assert_eq!(telemetry.device_orientation, None);
+        assert!(
+            (telemetry.heading.unwrap() - 90.0).abs() < 1e-3,
+            "{telemetry:?}"
+        );
+        assert!(telemetry.pitch.unwrap().abs() < 1e-3, "{telemetry:?}");
+        assert!(telemetry.roll.unwrap().abs() < 1e-3, "{telemetry:?}");
+    }
+}
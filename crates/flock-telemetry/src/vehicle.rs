@@ -0,0 +1,669 @@
+use std::{
+    collections::{BTreeMap, VecDeque},
+    fmt,
+    net::SocketAddr,
+    ops::{Deref, DerefMut},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        mpsc, Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use crate::{
+    calibration::{Calibration, CalibrationError, Calibrations},
+    command::{Ack, Command, CommandId, Outbound, SentCommand},
+    connection::{Connection, ConnectionId},
+    orientation::Attitude,
+    status::ParseError,
+    telemetry::{Position, Telemetry},
+};
+
+/// Number of positions kept in the breadcrumb trail of each vehicle
+const TRACK_LEN: usize = 2000;
+/// How far back the telemetry history of each vehicle goes
+pub const HISTORY_AGE: Duration = Duration::from_secs(30 * 60);
+/// Most telemetry updates kept in the history of each vehicle, [`HISTORY_AGE`] at 50 Hz
+///
+/// Vehicles sending faster than that keep a shorter history, rather than growing without bound.
+const HISTORY_LEN: usize = 90_000;
+/// Number of sent commands kept in the command log of each vehicle
+const COMMAND_LOG_LEN: usize = 20;
+/// Number of telemetry updates waiting for the UI, a few seconds of a busy flock
+const EVENT_CAPACITY: usize = 16_384;
+
+/// Registry of every vehicle that has ever connected, keyed by its id
+///
+/// Owned by the UI thread. Ingest threads never touch it directly, they send [`VehicleEvent`]s
+/// through a [`VehicleUpdates`] handle instead, which the UI applies with [`Vehicles::drain`] at
+/// the start of every frame. This way a slow frame never blocks the network, and every single
+/// update reaches the vehicle history rather than just the latest one.
+///
+/// Should the UI fall behind by more than [`EVENT_CAPACITY`] updates, telemetry is dropped rather
+/// than queued without bound, see [`Vehicles::dropped_events`]. Connections opening and closing
+/// and commands being answered are rare enough to queue on a channel of their own, so they are
+/// never dropped nor held up by the telemetry.
+pub struct Vehicles {
+    vehicles: BTreeMap<VehicleId, Vehicle>,
+    /// Telemetry updates and parse failures
+    events: mpsc::Receiver<(VehicleId, VehicleEvent)>,
+    /// Every other event
+    control: mpsc::Receiver<(VehicleId, VehicleEvent)>,
+    dropped: Arc<AtomicU64>,
+    calibrations: Calibrations,
+}
+
+/// Something that happened to a vehicle on an ingest thread
+#[derive(Debug, Clone)]
+pub enum VehicleEvent {
+    /// A connection feeding the vehicle was opened
+    Connected(ConnectionId, Connection),
+    /// A connection feeding the vehicle was closed, or timed out
+    Disconnected(ConnectionId),
+    Telemetry {
+        connection: ConnectionId,
+        telemetry: Telemetry,
+        /// When the update was received
+        time: Instant,
+        /// Size of the message on the wire
+        bytes: usize,
+    },
+    ParseFailed {
+        connection: ConnectionId,
+        error: ParseError,
+        time: Instant,
+        bytes: usize,
+    },
+    /// A source answered a command
+    Acknowledged { ack: Ack, time: Instant },
+}
+
+/// Sending half of [`Vehicles`], cloned into every ingest thread
+#[derive(Clone)]
+pub struct VehicleUpdates {
+    events: mpsc::SyncSender<(VehicleId, VehicleEvent)>,
+    control: mpsc::Sender<(VehicleId, VehicleEvent)>,
+    /// Events dropped because the UI fell behind
+    dropped: Arc<AtomicU64>,
+}
+
+impl Vehicles {
+    /// An empty registry, and the handle to feed it with
+    pub fn new(calibrations: Calibrations) -> (Self, VehicleUpdates) {
+        let (sender, events) = mpsc::sync_channel(EVENT_CAPACITY);
+        let (control_sender, control) = mpsc::channel();
+        let dropped = Arc::new(AtomicU64::new(0));
+
+        (
+            Self {
+                vehicles: BTreeMap::new(),
+                events,
+                control,
+                dropped: dropped.clone(),
+                calibrations,
+            },
+            VehicleUpdates {
+                events: sender,
+                control: control_sender,
+                dropped,
+            },
+        )
+    }
+
+    /// The vehicle called `id`, added with its saved calibration if it is new
+    pub fn get_or_insert(&mut self, id: &VehicleId) -> &mut Vehicle {
+        self.vehicles.entry(id.clone()).or_insert_with(|| Vehicle {
+            calibration: self.calibrations.get(id),
+            ..Default::default()
+        })
+    }
+
+    /// Change the calibration of `id`, correcting its current attitude right away
+    ///
+    /// The change is only written to disk by [`Vehicles::save_calibrations`].
+    pub fn set_calibration(&mut self, id: &VehicleId, calibration: Calibration) {
+        self.get_or_insert(id).set_calibration(calibration);
+        self.calibrations.set(id, calibration);
+    }
+
+    pub fn save_calibrations(&self) -> Result<(), CalibrationError> {
+        self.calibrations.save()
+    }
+
+    /// Number of telemetry updates dropped because the UI fell behind
+    pub fn dropped_events(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Apply every event sent since the last call
+    pub fn drain(&mut self) {
+        self.drain_control();
+
+        while let Ok((id, event)) = self.events.try_recv() {
+            if let VehicleEvent::Telemetry { connection, .. }
+            | VehicleEvent::ParseFailed { connection, .. } = &event
+            {
+                // Opened after the control events were drained
+                if !self
+                    .vehicles
+                    .get(&id)
+                    .is_some_and(|vehicle| vehicle.connections.contains_key(connection))
+                {
+                    self.drain_control();
+                }
+            }
+
+            self.apply(&id, event);
+        }
+    }
+
+    fn drain_control(&mut self) {
+        while let Ok((id, event)) = self.control.try_recv() {
+            self.apply(&id, event);
+        }
+    }
+
+    fn apply(&mut self, id: &VehicleId, event: VehicleEvent) {
+        let vehicle = self.get_or_insert(id);
+
+        match event {
+            VehicleEvent::Connected(id, connection) => {
+                vehicle.connections.insert(id, connection);
+            }
+            VehicleEvent::Disconnected(id) => {
+                vehicle.connections.remove(&id);
+            }
+            VehicleEvent::Telemetry {
+                connection,
+                telemetry,
+                time,
+                bytes,
+            } => {
+                if let Some(connection) = vehicle.connections.get_mut(&connection) {
+                    connection.received(time, bytes);
+                }
+                vehicle.update(&telemetry, time);
+            }
+            VehicleEvent::ParseFailed {
+                connection,
+                error,
+                time,
+                bytes,
+            } => {
+                if let Some(connection) = vehicle.connections.get_mut(&connection) {
+                    connection.received(time, bytes);
+                    connection.parse_errors += 1;
+                }
+                vehicle.parse_errors += 1;
+                vehicle.last_parse_error = Some(error);
+            }
+            VehicleEvent::Acknowledged { ack, time } => {
+                if let Some(sent) = vehicle.commands.iter_mut().find(|sent| sent.id == ack.id) {
+                    let result = if ack.ok {
+                        Ok(())
+                    } else {
+                        Err(ack.error.unwrap_or_else(|| "no reason given".to_owned()))
+                    };
+
+                    sent.reply = Some((time, result));
+                }
+            }
+        }
+    }
+}
+
+impl Deref for Vehicles {
+    type Target = BTreeMap<VehicleId, Vehicle>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.vehicles
+    }
+}
+
+impl DerefMut for Vehicles {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.vehicles
+    }
+}
+
+impl VehicleUpdates {
+    /// Send an event for `id`, dropped if the UI is gone
+    ///
+    /// Updates are dropped as well while the UI is behind, but connections opening and closing,
+    /// and commands being answered, are always delivered. Never blocks.
+    pub fn send(&self, id: &VehicleId, event: VehicleEvent) {
+        let event = (id.clone(), event);
+
+        match event.1 {
+            VehicleEvent::Telemetry { .. } | VehicleEvent::ParseFailed { .. } => {
+                if let Err(mpsc::TrySendError::Full(_)) = self.events.try_send(event) {
+                    self.dropped.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+            VehicleEvent::Connected(..)
+            | VehicleEvent::Disconnected(_)
+            | VehicleEvent::Acknowledged { .. } => {
+                let _ = self.control.send(event);
+            }
+        }
+    }
+
+    /// A connection opened just now, identified by the returned id in later events
+    pub fn connected(
+        &self,
+        id: &VehicleId,
+        transport: &'static str,
+        peer: impl Into<String>,
+    ) -> ConnectionId {
+        let connection = ConnectionId::next();
+        self.send(
+            id,
+            VehicleEvent::Connected(connection, Connection::new(transport, peer, Instant::now())),
+        );
+
+        connection
+    }
+
+    /// Like [`connected`](Self::connected), for a connection that accepts commands
+    ///
+    /// The commands the operator sends arrive on the returned receiver.
+    pub fn connected_with_commands(
+        &self,
+        id: &VehicleId,
+        transport: &'static str,
+        peer: impl Into<String>,
+    ) -> (ConnectionId, mpsc::Receiver<Outbound>) {
+        let (sender, commands) = mpsc::channel();
+        let connection = ConnectionId::next();
+        self.send(
+            id,
+            VehicleEvent::Connected(
+                connection,
+                Connection::new(transport, peer, Instant::now()).with_commands(sender),
+            ),
+        );
+
+        (connection, commands)
+    }
+
+    pub fn disconnected(&self, id: &VehicleId, connection: ConnectionId) {
+        self.send(id, VehicleEvent::Disconnected(connection));
+    }
+
+    /// A telemetry update of `bytes` received just now
+    pub fn telemetry(
+        &self,
+        id: &VehicleId,
+        connection: ConnectionId,
+        telemetry: Telemetry,
+        bytes: usize,
+    ) {
+        self.send(
+            id,
+            VehicleEvent::Telemetry {
+                connection,
+                telemetry,
+                time: Instant::now(),
+                bytes,
+            },
+        );
+    }
+
+    pub fn acknowledged(&self, id: &VehicleId, ack: Ack) {
+        self.send(
+            id,
+            VehicleEvent::Acknowledged {
+                ack,
+                time: Instant::now(),
+            },
+        );
+    }
+
+    pub fn parse_failed(
+        &self,
+        id: &VehicleId,
+        connection: ConnectionId,
+        error: impl fmt::Display,
+        payload: impl Into<String>,
+    ) {
+        let payload = payload.into();
+
+        self.send(
+            id,
+            VehicleEvent::ParseFailed {
+                connection,
+                time: Instant::now(),
+                bytes: payload.len(),
+                error: ParseError {
+                    error: error.to_string(),
+                    payload,
+                },
+            },
+        );
+    }
+}
+
+/// Identifies a single attitude source in the flock
+///
+/// Either supplied by the client in the `vehicle` query parameter of the
+/// websocket handshake or the `vehicle` field of a UDP or TCP message, or
+/// derived from the peer address.
+#[derive(
+    Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, serde::Serialize, serde::Deserialize,
+)]
+#[serde(transparent)]
+pub struct VehicleId(String);
+
+impl VehicleId {
+    pub fn new(id: impl Into<String>) -> Self {
+        Self(id.into())
+    }
+
+    pub fn from_peer(peer: SocketAddr) -> Self {
+        Self(peer.ip().to_string())
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for VehicleId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct Vehicle {
+    /// Latest known state, merged from every message received
+    pub telemetry: Telemetry,
+    /// Recent positions, oldest first
+    pub track: VecDeque<Position>,
+    /// Recent updates as received, with the time they applied to, oldest first
+    pub history: VecDeque<(Instant, Telemetry)>,
+    /// Currently open connections feeding this vehicle
+    pub connections: BTreeMap<ConnectionId, Connection>,
+    /// Number of messages from this vehicle that could not be decoded
+    pub parse_errors: usize,
+    pub last_parse_error: Option<ParseError>,
+    /// Recently sent commands, oldest first
+    pub commands: VecDeque<SentCommand>,
+    /// Correction of the attitude for how the sensor is mounted
+    pub calibration: Calibration,
+    /// Latest attitude as measured by the sensor, before calibration
+    pub sensor_attitude: Option<Attitude>,
+    /// The state merged from the messages received while frozen, see [`freeze`](Self::freeze)
+    pub live: Option<Telemetry>,
+}
+
+impl Vehicle {
+    pub fn connected(&self) -> bool {
+        !self.connections.is_empty()
+    }
+
+    /// Time since the newest data arrived over any open connection
+    pub fn age(&self, now: Instant) -> Option<Duration> {
+        self.connections
+            .values()
+            .map(|connection| connection.age(now))
+            .min()
+    }
+
+    /// Whether the vehicle is connected, but nothing arrived within `timeout`
+    pub fn is_stale(&self, now: Instant, timeout: Duration) -> bool {
+        self.age(now).is_some_and(|age| age > timeout)
+    }
+
+    /// Whether [`telemetry`](Self::telemetry) is frozen, rather than following the messages
+    pub fn frozen(&self) -> bool {
+        self.live.is_some()
+    }
+
+    /// Stop merging received messages into [`telemetry`](Self::telemetry), for the operator to
+    /// change it by hand
+    ///
+    /// The messages are still merged into [`live`](Self::live), recorded in the history and
+    /// extend the track, so nothing is lost once unfrozen.
+    pub fn freeze(&mut self) {
+        if self.live.is_none() {
+            self.live = Some(self.telemetry.clone());
+        }
+    }
+
+    /// Follow the received messages again, dropping the changes made while frozen
+    pub fn unfreeze(&mut self) {
+        if let Some(live) = self.live.take() {
+            self.telemetry = live;
+        }
+    }
+
+    /// Merge a telemetry update into the latest state, extending the track if it moved
+    pub fn update(&mut self, update: &Telemetry, time: Instant) {
+        let update = &self.calibrate(update);
+        self.live
+            .as_mut()
+            .unwrap_or(&mut self.telemetry)
+            .merge(update);
+
+        if self.history.len() == HISTORY_LEN {
+            self.history.pop_front();
+        }
+        self.history.push_back((time, update.clone()));
+        while self
+            .history
+            .front()
+            .is_some_and(|(oldest, _)| time.saturating_duration_since(*oldest) > HISTORY_AGE)
+        {
+            self.history.pop_front();
+        }
+
+        if let Some(position) = update.position {
+            if self.track.back() != Some(&position) {
+                if self.track.len() == TRACK_LEN {
+                    self.track.pop_front();
+                }
+                self.track.push_back(position);
+            }
+        }
+    }
+
+    /// Send `command` over `connection`, logging it to track its acknowledgement
+    pub fn send_command(&mut self, connection: ConnectionId, command: Command) {
+        let id = CommandId::next();
+        let now = Instant::now();
+
+        let sent = self
+            .connections
+            .get(&connection)
+            .and_then(|connection| connection.commands.as_ref())
+            .is_some_and(|commands| {
+                commands
+                    .send(Outbound {
+                        id,
+                        command: command.clone(),
+                    })
+                    .is_ok()
+            });
+
+        if self.commands.len() == COMMAND_LOG_LEN {
+            self.commands.pop_front();
+        }
+        self.commands.push_back(SentCommand {
+            id,
+            command,
+            connection,
+            sent_at: now,
+            reply: (!sent).then(|| (now, Err("connection closed".to_owned()))),
+        });
+    }
+
+    /// Correct the attitude in `update` with the calibration
+    ///
+    /// The corrected attitude depends on all three angles, so when an update carries any of
+    /// them, it is completed with the latest measurement of the others.
+    fn calibrate(&mut self, update: &Telemetry) -> Telemetry {
+        let mut update = update.clone();
+        if update.heading.is_none() && update.pitch.is_none() && update.roll.is_none() {
+            return update;
+        }
+
+        let sensor = self.sensor_attitude.get_or_insert_with(Attitude::default);
+        sensor.heading = update.heading.unwrap_or(sensor.heading);
+        sensor.pitch = update.pitch.unwrap_or(sensor.pitch);
+        sensor.roll = update.roll.unwrap_or(sensor.roll);
+
+        if !self.calibration.is_identity() {
+            let attitude = self.calibration.apply(*sensor);
+
+            update.heading = Some(attitude.heading);
+            update.pitch = Some(attitude.pitch);
+            update.roll = Some(attitude.roll);
+        }
+
+        update
+    }
+
+    fn set_calibration(&mut self, calibration: Calibration) {
+        self.calibration = calibration;
+
+        if let Some(sensor) = self.sensor_attitude {
+            let attitude = calibration.apply(sensor);
+            let telemetry = self.live.as_mut().unwrap_or(&mut self.telemetry);
+
+            telemetry.heading = Some(attitude.heading);
+            telemetry.pitch = Some(attitude.pitch);
+            telemetry.roll = Some(attitude.roll);
+        }
+    }
+
+    /// Forget everything known about the state of the vehicle
+    pub fn reset(&mut self) {
+        self.telemetry = Telemetry::default();
+        if let Some(live) = &mut self.live {
+            *live = Telemetry::default();
+        }
+        self.sensor_attitude = None;
+        self.track.clear();
+        self.history.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+
+    #[test]
+    fn freeze() {
+        let mut vehicle = Vehicle::default();
+        let now = Instant::now();
+        let attitude = |heading, pitch| Telemetry {
+            heading: Some(heading),
+            pitch: Some(pitch),
+            ..Default::default()
+        };
+
+        vehicle.update(&attitude(10.0, 5.0), now);
+        vehicle.freeze();
+        vehicle.telemetry.pitch = Some(45.0);
+        vehicle.update(&attitude(20.0, 6.0), now);
+
+        // Shows the nudged state, while the updates are still kept
+        assert_eq!(vehicle.telemetry.heading, Some(10.0));
+        assert_eq!(vehicle.telemetry.pitch, Some(45.0));
+        assert_eq!(vehicle.history.len(), 2);
+
+        vehicle.unfreeze();
+        assert_eq!(vehicle.telemetry.heading, Some(20.0));
+        assert_eq!(vehicle.telemetry.pitch, Some(6.0));
+    }
+
+    #[test]
+    fn full_queue() {
+        let (mut vehicles, updates) = Vehicles::new(Calibrations::default());
+        let id = VehicleId::new("busy");
+        let connection = updates.connected(&id, "test", "peer");
+
+        for _ in 0..=EVENT_CAPACITY {
+            updates.telemetry(&id, connection, Telemetry::default(), 10);
+        }
+        assert_eq!(vehicles.dropped_events(), 1);
+
+        vehicles.drain();
+        assert_eq!(vehicles[&id].history.len(), EVENT_CAPACITY);
+        assert!(vehicles[&id].connections.contains_key(&connection));
+
+        // Room again once the UI caught up
+        updates.telemetry(&id, connection, Telemetry::default(), 10);
+        updates.disconnected(&id, connection);
+        vehicles.drain();
+        assert_eq!(vehicles[&id].history.len(), EVENT_CAPACITY + 1);
+        assert!(vehicles[&id].connections.is_empty());
+    }
+
+    #[test]
+    fn full_queue_connects() {
+        let (mut vehicles, updates) = Vehicles::new(Calibrations::default());
+        let busy = VehicleId::new("busy");
+        let connection = updates.connected(&busy, "test", "peer");
+
+        for _ in 0..=EVENT_CAPACITY {
+            updates.telemetry(&busy, connection, Telemetry::default(), 10);
+        }
+
+        // With the queue full, connecting does not wait for the UI
+        let (done, finished) = mpsc::channel();
+        thread::spawn({
+            let updates = updates.clone();
+
+            let busy = busy.clone();
+
+            move || {
+                let id = VehicleId::new("new");
+                let new = updates.connected(&id, "test", "peer");
+                updates.telemetry(&id, new, Telemetry::default(), 10);
+                updates.disconnected(&busy, connection);
+                let _ = done.send(new);
+            }
+        });
+        let new = finished
+            .recv_timeout(Duration::from_secs(5))
+            .expect("connecting blocked on the full queue");
+
+        vehicles.drain();
+        assert!(vehicles[&VehicleId::new("new")]
+            .connections
+            .contains_key(&new));
+        assert!(vehicles[&VehicleId::new("new")].history.is_empty());
+        assert!(vehicles[&busy].connections.is_empty());
+        assert_eq!(vehicles.dropped_events(), 2);
+    }
+
+    #[test]
+    fn history_age() {
+        let mut vehicle = Vehicle::default();
+        let start = Instant::now();
+        let update = Telemetry {
+            airspeed: Some(12.0),
+            ..Default::default()
+        };
+
+        // An hour at 10 Hz, more than any fixed number of updates would hold
+        for tick in 0..36_000 {
+            vehicle.update(&update, start + Duration::from_millis(tick * 100));
+        }
+
+        let (oldest, _) = vehicle.history.front().unwrap();
+        let (newest, _) = vehicle.history.back().unwrap();
+        assert_eq!(*newest - *oldest, HISTORY_AGE);
+        assert_eq!(vehicle.history.len(), 18_001);
+
+        // Ten minutes at 200 Hz, more than any history should hold
+        for tick in 0..120_000 {
+            vehicle.update(&update, start + Duration::from_millis(3_600_000 + tick * 5));
+        }
+        assert_eq!(vehicle.history.len(), HISTORY_LEN);
+    }
+}
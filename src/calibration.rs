@@ -0,0 +1,100 @@
+//! The calibration panel of the selected vehicle, see [`flock_telemetry::calibration`]
+
+use eframe::egui::{self, DragValue};
+use flock_telemetry::{calibration::Calibration, vehicle::Vehicle};
+
+/// A change to the calibration of a vehicle
+pub struct Edit {
+    pub calibration: Calibration,
+    /// Whether the operator is done changing it, so it is worth saving
+    pub finished: bool,
+}
+
+/// Edit the calibration of `vehicle`, returning what changed or finished changing
+pub fn ui(ui: &mut egui::Ui, vehicle: &Vehicle) -> Option<Edit> {
+    let mut calibration = vehicle.calibration;
+    let mut finished = false;
+
+    egui::Grid::new("calibration").show(ui, |ui| {
+        let mounting = &mut calibration.mounting;
+
+        ui.label("Mounting");
+        finished |= done(
+            &ui.add(
+                DragValue::new(&mut mounting.heading)
+                    .range(0.0..=359.0)
+                    .prefix("heading ")
+                    .suffix("°"),
+            ),
+        );
+        finished |= done(
+            &ui.add(
+                DragValue::new(&mut mounting.pitch)
+                    .range(-90.0..=90.0)
+                    .prefix("pitch ")
+                    .suffix("°"),
+            ),
+        );
+        finished |= done(
+            &ui.add(
+                DragValue::new(&mut mounting.roll)
+                    .range(-180.0..=180.0)
+                    .prefix("roll ")
+                    .suffix("°"),
+            ),
+        );
+        if ui
+            .button("Rotate 90°")
+            .on_hover_text("For a sensor mounted sideways")
+            .clicked()
+        {
+            mounting.heading = (mounting.heading + 90.0).rem_euclid(360.0);
+            finished = true;
+        }
+        ui.end_row();
+
+        ui.label("Level");
+        match calibration.level {
+            Some(level) => {
+                ui.label(format!("pitch {:.1}°", level.pitch));
+                ui.label(format!("roll {:.1}°", level.roll));
+            }
+            None => {
+                ui.label("not captured");
+                ui.label("");
+            }
+        }
+
+        let sensor = vehicle.sensor_attitude;
+        if ui
+            .add_enabled(sensor.is_some(), egui::Button::new("Level here"))
+            .on_hover_text("Take the current attitude of the aircraft as level")
+            .clicked()
+        {
+            if let Some(sensor) = sensor {
+                calibration.capture_level(sensor);
+            }
+            finished = true;
+        }
+        if ui.button("Clear").clicked() {
+            calibration.level = None;
+            finished = true;
+        }
+        ui.end_row();
+    });
+
+    if ui.button("Reset calibration").clicked() {
+        calibration = Calibration::default();
+        finished = true;
+    }
+
+    (finished || calibration != vehicle.calibration).then_some(Edit {
+        calibration,
+        finished,
+    })
+}
+
+/// Whether a drag or typed value is complete, rather than still changing every frame
+fn done(response: &egui::Response) -> bool {
+    response.drag_stopped() || response.lost_focus()
+}
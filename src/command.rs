@@ -0,0 +1,146 @@
+//! The command panel of the selected vehicle, see [`flock_telemetry::command`]
+
+use std::time::{Duration, Instant};
+
+use eframe::egui::{self, Color32, RichText};
+use flock_telemetry::{
+    command::{Command, CommandState},
+    connection::ConnectionId,
+    vehicle::Vehicle,
+};
+
+/// The command panel of the selected vehicle
+pub struct Commands {
+    /// Connection commands go to, when the vehicle has several that accept them
+    connection: Option<ConnectionId>,
+    mode: String,
+    heading: f32,
+}
+
+impl Default for Commands {
+    fn default() -> Self {
+        Self {
+            connection: None,
+            mode: "AUTO".to_owned(),
+            heading: 0.0,
+        }
+    }
+}
+
+impl Commands {
+    /// Show the panel, returning the command the operator sent and where to, if any
+    pub fn ui(&mut self, ui: &mut egui::Ui, vehicle: &Vehicle) -> Option<(ConnectionId, Command)> {
+        let now = Instant::now();
+        let targets = vehicle
+            .connections
+            .iter()
+            .filter(|(_, connection)| connection.commands.is_some())
+            .collect::<Vec<_>>();
+
+        if !targets.iter().any(|(id, _)| Some(**id) == self.connection) {
+            self.connection = targets.first().map(|(id, _)| **id);
+        }
+
+        let mut command = None;
+
+        match self.connection {
+            None => {
+                ui.label("No connection of this vehicle accepts commands");
+            }
+            Some(ref mut selected) => {
+                if targets.len() > 1 {
+                    let label = |id: &ConnectionId| {
+                        vehicle
+                            .connections
+                            .get(id)
+                            .map_or(String::new(), |connection| {
+                                format!("{} {}", connection.transport, connection.peer)
+                            })
+                    };
+
+                    egui::ComboBox::from_label("Send to")
+                        .selected_text(label(selected))
+                        .show_ui(ui, |ui| {
+                            for (id, _) in &targets {
+                                ui.selectable_value(selected, **id, label(id));
+                            }
+                        });
+                }
+
+                ui.horizontal(|ui| {
+                    if ui.button("Arm").clicked() {
+                        command = Some(Command::Arm);
+                    }
+                    if ui.button("Disarm").clicked() {
+                        command = Some(Command::Disarm);
+                    }
+                    if ui.button("Zero orientation").clicked() {
+                        command = Some(Command::ZeroOrientation);
+                    }
+                });
+
+                ui.horizontal(|ui| {
+                    ui.add(egui::TextEdit::singleline(&mut self.mode).desired_width(80.0));
+                    if ui.button("Set mode").clicked() {
+                        command = Some(Command::SetMode {
+                            mode: self.mode.trim().to_owned(),
+                        });
+                    }
+
+                    ui.separator();
+
+                    ui.add(
+                        egui::DragValue::new(&mut self.heading)
+                            .range(0.0..=359.0)
+                            .suffix("°"),
+                    );
+                    if ui.button("Set heading").clicked() {
+                        command = Some(Command::SetHeading {
+                            heading: self.heading,
+                        });
+                    }
+                });
+            }
+        }
+
+        let mut pending = false;
+
+        egui::Grid::new("commands").striped(true).show(ui, |ui| {
+            for sent in vehicle.commands.iter().rev() {
+                ui.label(sent.command.to_string());
+                ui.monospace(format!(
+                    "{:.1} s ago",
+                    now.saturating_duration_since(sent.sent_at).as_secs_f32()
+                ));
+
+                match sent.state(now) {
+                    CommandState::Pending => {
+                        pending = true;
+                        ui.label(RichText::new("waiting for ack").color(Color32::YELLOW));
+                    }
+                    CommandState::Acknowledged(after) => {
+                        ui.label(
+                            RichText::new(format!("acknowledged in {} ms", after.as_millis()))
+                                .color(Color32::GREEN),
+                        );
+                    }
+                    CommandState::Rejected(error) => {
+                        ui.label(RichText::new(format!("rejected: {error}")).color(Color32::RED));
+                    }
+                    CommandState::TimedOut => {
+                        ui.label(RichText::new("timed out").color(Color32::RED));
+                    }
+                }
+
+                ui.end_row();
+            }
+        });
+
+        if pending {
+            // Show the timeout as soon as it expires
+            ui.ctx().request_repaint_after(Duration::from_millis(250));
+        }
+
+        Some((self.connection?, command?))
+    }
+}
@@ -1,3 +0,0 @@
-pub mod heading;
-pub mod attitude;
-// pub mod drag_space;
\ No newline at end of file
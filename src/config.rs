@@ -0,0 +1,308 @@
+//! Runtime configuration, read from a TOML config file and overridden by command line arguments
+
+use std::{
+    fs, io,
+    net::IpAddr,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use clap::Parser;
+use flock_telemetry::config::{
+    Framing, MavlinkConfig, Scenario, SerialConfig, ServerConfig, SimulatorConfig, TcpConfig,
+    TlsConfig, UdpConfig,
+};
+use serde::Deserialize;
+
+use crate::render::RenderArgs;
+
+/// Config file read when `--config` is not given, if it exists
+const DEFAULT_CONFIG_PATH: &str = "flock.toml";
+
+/// Longest stale timeout accepted, anything longer turns stale detection off in all but name
+const MAX_STALE_TIMEOUT: f64 = 3600.0;
+
+#[derive(Debug, Parser)]
+#[command(version, about = "Aero Flock ground station")]
+pub struct Args {
+    /// TOML config file [default: flock.toml, if present]
+    #[arg(long)]
+    pub config: Option<PathBuf>,
+
+    /// Address the telemetry server binds to
+    #[arg(long)]
+    pub bind: Option<IpAddr>,
+
+    /// Port the telemetry server listens on
+    #[arg(long)]
+    pub port: Option<u16>,
+
+    /// Do not start the websocket server
+    #[arg(long)]
+    pub no_websocket: bool,
+
+    /// PEM certificate (chain) to serve `wss://` with, requires `--tls-key`
+    #[arg(long, requires = "tls_key")]
+    pub tls_cert: Option<PathBuf>,
+
+    /// PEM PKCS#8 private key belonging to `--tls-cert`
+    #[arg(long, requires = "tls_cert")]
+    pub tls_key: Option<PathBuf>,
+
+    /// UDP port to listen for MAVLink on
+    #[arg(long)]
+    pub mavlink_port: Option<u16>,
+
+    /// Do not listen for MAVLink
+    #[arg(long)]
+    pub no_mavlink: bool,
+
+    /// Listen for JSON telemetry datagrams on this UDP port
+    #[arg(long)]
+    pub udp_port: Option<u16>,
+
+    /// Listen for newline delimited JSON telemetry on this TCP port
+    #[arg(long)]
+    pub tcp_port: Option<u16>,
+
+    /// Serial device to read telemetry from, like a telemetry radio
+    #[arg(long)]
+    pub serial: Option<PathBuf>,
+
+    /// Baud rate of the serial device
+    #[arg(long)]
+    pub baud: Option<u32>,
+
+    /// How messages are framed on the serial device
+    #[arg(long, value_enum)]
+    pub serial_framing: Option<Framing>,
+
+    /// Start this many simulated vehicles
+    #[arg(long, value_name = "VEHICLES")]
+    pub simulate: Option<usize>,
+
+    /// What the simulated vehicles fly
+    #[arg(long, value_enum)]
+    pub scenario: Option<Scenario>,
+
+    /// Directory of offline map tiles, laid out as `<zoom>/<x>/<y>.png`
+    #[arg(long)]
+    pub tiles: Option<PathBuf>,
+
+    /// Seconds without telemetry before a connected vehicle is flagged as stale
+    #[arg(long)]
+    pub stale_timeout: Option<f64>,
+
+    /// File the calibration of every vehicle is saved to
+    #[arg(long)]
+    pub calibration: Option<PathBuf>,
+
+    /// Recording to replay at startup
+    #[arg(long)]
+    pub replay: Option<PathBuf>,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+/// Something to do instead of opening the ground station
+#[derive(Debug, clap::Subcommand)]
+pub enum Command {
+    /// Render the instruments to PNG images, without opening a window
+    Render(RenderArgs),
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub server: ServerConfig,
+    pub mavlink: MavlinkConfig,
+    pub udp: UdpConfig,
+    pub tcp: TcpConfig,
+    pub serial: SerialConfig,
+    pub simulator: SimulatorConfig,
+    pub map: MapConfig,
+    pub diagnostics: DiagnosticsConfig,
+    pub calibration: CalibrationConfig,
+    #[serde(skip)]
+    pub replay: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MapConfig {
+    /// Directory of offline raster tiles, laid out as `<zoom>/<x>/<y>.png`
+    pub tiles: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DiagnosticsConfig {
+    /// Seconds without telemetry before a connected vehicle is flagged as stale
+    pub stale_timeout: f64,
+}
+
+impl Default for DiagnosticsConfig {
+    fn default() -> Self {
+        // Phones send orientation at 10 Hz or more, MAVLink heartbeats come at 1 Hz
+        Self { stale_timeout: 2.0 }
+    }
+}
+
+impl DiagnosticsConfig {
+    pub fn stale_timeout(&self) -> Duration {
+        Duration::from_secs_f64(self.stale_timeout)
+    }
+
+    fn validate(&self) -> Result<(), ConfigError> {
+        let timeout = self.stale_timeout;
+
+        if timeout > 0.0 && timeout <= MAX_STALE_TIMEOUT {
+            Ok(())
+        } else {
+            Err(ConfigError::Invalid(format!(
+                "stale timeout {timeout} must be a positive number of seconds, up to \
+                 {MAX_STALE_TIMEOUT}"
+            )))
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CalibrationConfig {
+    /// File the calibration of every vehicle is saved to
+    pub path: PathBuf,
+}
+
+impl Default for CalibrationConfig {
+    fn default() -> Self {
+        Self {
+            path: PathBuf::from("flock-calibration.toml"),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Read(PathBuf, io::Error),
+    Parse(PathBuf, toml::de::Error),
+    /// A value out of range, from the config file or the command line
+    Invalid(String),
+}
+
+impl std::fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigError::Read(path, error) => {
+                write!(f, "failed to read {}: {error}", path.display())
+            }
+            ConfigError::Parse(path, error) => {
+                write!(f, "failed to parse {}: {error}", path.display())
+            }
+            ConfigError::Invalid(error) => f.write_str(error),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl Config {
+    /// Load the config file named by `args` (or the default one) and apply the argument overrides
+    pub fn load(args: Args) -> Result<Self, ConfigError> {
+        let mut config = match &args.config {
+            Some(path) => Self::read(path)?,
+            None if Path::new(DEFAULT_CONFIG_PATH).exists() => Self::read(DEFAULT_CONFIG_PATH)?,
+            None => Self::default(),
+        };
+
+        if let Some(bind) = args.bind {
+            config.server.bind = bind;
+        }
+        if let Some(port) = args.port {
+            config.server.port = port;
+        }
+        if let (Some(cert), Some(key)) = (args.tls_cert, args.tls_key) {
+            config.server.tls = Some(TlsConfig { cert, key });
+        }
+        if args.no_websocket {
+            config.server.enabled = false;
+        }
+        if let Some(port) = args.mavlink_port {
+            config.mavlink.port = port;
+        }
+        if args.no_mavlink {
+            config.mavlink.enabled = false;
+        }
+        if let Some(port) = args.udp_port {
+            config.udp.enabled = true;
+            config.udp.port = port;
+        }
+        if let Some(port) = args.tcp_port {
+            config.tcp.enabled = true;
+            config.tcp.port = port;
+        }
+        if let Some(device) = args.serial {
+            config.serial.enabled = true;
+            config.serial.device = device;
+        }
+        if let Some(baud) = args.baud {
+            config.serial.baud = baud;
+        }
+        if let Some(framing) = args.serial_framing {
+            config.serial.framing = framing;
+        }
+        if let Some(vehicles) = args.simulate {
+            config.simulator.enabled = true;
+            config.simulator.vehicles = vehicles;
+        }
+        if let Some(scenario) = args.scenario {
+            config.simulator.scenario = scenario;
+        }
+        if let Some(tiles) = args.tiles {
+            config.map.tiles = Some(tiles);
+        }
+        if let Some(timeout) = args.stale_timeout {
+            config.diagnostics.stale_timeout = timeout;
+        }
+        if let Some(path) = args.calibration {
+            config.calibration.path = path;
+        }
+        config.replay = args.replay;
+
+        config.diagnostics.validate()?;
+
+        Ok(config)
+    }
+
+    fn read(path: impl AsRef<Path>) -> Result<Self, ConfigError> {
+        let path = path.as_ref();
+        let contents =
+            fs::read_to_string(path).map_err(|error| ConfigError::Read(path.to_owned(), error))?;
+
+        toml::from_str(&contents).map_err(|error| ConfigError::Parse(path.to_owned(), error))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stale_timeout() {
+        let diagnostics = |text: &str| toml::from_str::<Config>(text).unwrap().diagnostics;
+
+        let valid = diagnostics("[diagnostics]\nstale_timeout = 0.5");
+        assert!(valid.validate().is_ok());
+        assert_eq!(valid.stale_timeout(), Duration::from_millis(500));
+        assert!(diagnostics("").validate().is_ok());
+
+        for invalid in ["0", "-1.0", "nan", "inf", "1e300"] {
+            let config = diagnostics(&format!("[diagnostics]\nstale_timeout = {invalid}"));
+            assert!(config.validate().is_err(), "{invalid} is accepted");
+        }
+
+        let args = Args::parse_from(["flock", "--config", "/dev/null", "--stale-timeout=-2"]);
+        assert!(matches!(Config::load(args), Err(ConfigError::Invalid(_))));
+    }
+}
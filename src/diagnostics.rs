@@ -0,0 +1,131 @@
+//! Table of every open connection, to tell a frozen source from a stationary vehicle
+
+use std::time::{Duration, Instant};
+
+use eframe::egui::{self, Color32, RichText};
+use flock_telemetry::vehicle::Vehicles;
+
+/// How often the table refreshes when no telemetry arrives, so ages keep counting
+const REFRESH: Duration = Duration::from_millis(250);
+
+pub fn ui(ui: &mut egui::Ui, vehicles: &Vehicles, stale_timeout: Duration) {
+    let now = Instant::now();
+
+    if vehicles
+        .values()
+        .all(|vehicle| vehicle.connections.is_empty())
+    {
+        ui.label("No open connections");
+        return;
+    }
+
+    let dropped = vehicles.dropped_events();
+    if dropped > 0 {
+        ui.label(
+            RichText::new(format!("{dropped} updates dropped while the UI was behind"))
+                .color(Color32::YELLOW),
+        );
+    }
+
+    egui::ScrollArea::both().show(ui, |ui| {
+        egui::Grid::new("connections")
+            .striped(true)
+            .num_columns(10)
+            .show(ui, |ui| {
+                for heading in [
+                    "Vehicle",
+                    "Transport",
+                    "Peer",
+                    "Connected",
+                    "Rate",
+                    "Jitter",
+                    "Received",
+                    "Parse errors",
+                    "Last message",
+                    "",
+                ] {
+                    ui.strong(heading);
+                }
+                ui.end_row();
+
+                for (id, vehicle) in vehicles.iter() {
+                    for connection in vehicle.connections.values() {
+                        ui.label(id.as_str());
+                        ui.label(connection.transport);
+                        ui.monospace(&connection.peer);
+                        ui.monospace(format!(
+                            "{} ago",
+                            format_duration(now.saturating_duration_since(connection.connected_at))
+                        ));
+                        ui.monospace(format!("{:.1} Hz", connection.rate(now)));
+                        ui.monospace(connection.jitter().map_or("-".to_owned(), |jitter| {
+                            format!("{:.1} ms", jitter.as_secs_f64() * 1000.0)
+                        }));
+                        ui.monospace(format!(
+                            "{} in {} messages",
+                            format_bytes(connection.bytes),
+                            connection.messages
+                        ));
+
+                        if connection.parse_errors > 0 {
+                            ui.monospace(
+                                RichText::new(connection.parse_errors.to_string())
+                                    .color(Color32::YELLOW),
+                            );
+                        } else {
+                            ui.monospace("0");
+                        }
+
+                        ui.monospace(match connection.last_message {
+                            Some(_) => format!("{} ago", format_duration(connection.age(now))),
+                            None => "never".to_owned(),
+                        });
+
+                        if connection.is_stale(now, stale_timeout) {
+                            ui.label(RichText::new("STALE").strong().color(Color32::YELLOW))
+                                .on_hover_text(format!(
+                                    "Nothing received for over {}",
+                                    format_duration(stale_timeout)
+                                ));
+                        } else {
+                            ui.label(RichText::new("OK").color(Color32::GREEN));
+                        }
+
+                        ui.end_row();
+                    }
+                }
+            });
+    });
+
+    ui.ctx().request_repaint_after(REFRESH);
+}
+
+pub fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs_f64();
+
+    if seconds < 60.0 {
+        format!("{seconds:.1} s")
+    } else if seconds < 3600.0 {
+        format!(
+            "{}m {:02}s",
+            duration.as_secs() / 60,
+            duration.as_secs() % 60
+        )
+    } else {
+        format!(
+            "{}h {:02}m",
+            duration.as_secs() / 3600,
+            duration.as_secs() / 60 % 60
+        )
+    }
+}
+
+fn format_bytes(bytes: u64) -> String {
+    const KIB: f64 = 1024.0;
+
+    match bytes as f64 {
+        bytes if bytes < KIB => format!("{bytes} B"),
+        bytes if bytes < KIB * KIB => format!("{:.1} KiB", bytes / KIB),
+        bytes => format!("{:.1} MiB", bytes / KIB / KIB),
+    }
+}
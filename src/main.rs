@@ -1,19 +1,26 @@
-use std::{
-    net::TcpListener,
-    sync::{
-        atomic::{AtomicBool, Ordering},
-        Arc,
-    },
-    thread,
+use std::thread;
+
+use clap::Parser;
+use config::{Args, Command, Config};
+use flock_instruments::MapState;
+use flock_telemetry::{
+    calibration::Calibrations,
+    ingest::{self, Ingest},
+    recording::{Recorder, Replay},
+    status::Status,
+    vehicle::Vehicles,
 };
-
-use eframe::egui::mutex::Mutex;
-use tracing::{debug, trace, warn};
+use tracing::{debug, error};
 use tracing_subscriber::{prelude::__tracing_subscriber_SubscriberExt, util::SubscriberInitExt};
-use tungstenite::Message;
-use window::Attitude;
 
-pub mod component;
+pub mod calibration;
+pub mod command;
+pub mod config;
+pub mod diagnostics;
+pub mod manual;
+pub mod plot;
+pub mod render;
+pub mod simulator;
 pub mod window;
 
 fn main() -> Result<(), eframe::Error> {
@@ -30,6 +37,39 @@ fn main() -> Result<(), eframe::Error> {
         .with(tracing_subscriber::fmt::layer().pretty())
         .init();
 
+    let mut args = Args::parse();
+    let command = args.command.take();
+
+    let config = match Config::load(args) {
+        Ok(config) => config,
+        Err(error) => {
+            error!(%error, "invalid configuration");
+            std::process::exit(1);
+        }
+    };
+    debug!(?config, "loaded configuration");
+
+    if let Some(Command::Render(args)) = command {
+        if let Err(error) = render::run(args, &config) {
+            error!(%error, "rendering failed");
+            std::process::exit(1);
+        }
+
+        return Ok(());
+    }
+
+    let replay = config.replay.as_ref().and_then(|path| {
+        Replay::open(path)
+            .inspect_err(|error| error!(%error, path = %path.display(), "failed to open replay"))
+            .ok()
+    });
+
+    // Without the saved calibrations, do not overwrite them either
+    let calibrations = Calibrations::load(&config.calibration.path).unwrap_or_else(|error| {
+        error!(%error, "failed to load calibrations");
+        Calibrations::default()
+    });
+
     eframe::run_native(
         "Aero Flock",
         eframe::NativeOptions {
@@ -39,83 +79,55 @@ fn main() -> Result<(), eframe::Error> {
             ..Default::default()
         },
         Box::new(|ctx| {
-            let attitude = Arc::new(Mutex::new(Attitude {
-                heading: 200.0,
-                pitch: 10.0,
-                roll: 10.0,
-            }));
-            let connection = Arc::new(AtomicBool::new(false));
-
-            thread::spawn({
-                let attitude = attitude.clone();
-                let connection = connection.clone();
-                let request_repaint = {
-                    let ctx = ctx.egui_ctx.clone();
-
-                    move || ctx.request_repaint()
-                };
-
-                || websocket_thread(attitude, connection, request_repaint)
-            });
+            let (vehicles, updates) = Vehicles::new(calibrations);
+            let recorder = Recorder::default();
+            let status = Status::default();
 
-            Ok(Box::new(window::MainWindow::new(attitude, connection)))
-        }),
-    )?;
+            let ingest = Ingest::new(updates, recorder.clone(), status.clone(), {
+                let ctx = ctx.egui_ctx.clone();
 
-    Ok(())
-}
+                move || ctx.request_repaint()
+            });
 
-fn websocket_thread(
-    attitude_mutex: Arc<Mutex<Attitude>>,
-    connection: Arc<AtomicBool>,
-    request_repaint: impl Fn(),
-) {
-    let server = TcpListener::bind("0.0.0.0:8080").unwrap();
-    for stream in server.incoming() {
-        trace!("new TCP connection");
-        let mut websocket = tungstenite::accept(stream.unwrap()).unwrap();
-        trace!("TCP upgraded to websocket connection");
-        connection.store(true, Ordering::Release);
-        request_repaint();
-
-        loop {
-            match websocket.read() {
-                Ok(Message::Close(_)) => {
-                    break;
-                }
-                Ok(Message::Binary(_)) => {
-                    warn!("invalid message type: binary");
-                    break;
-                }
-                Ok(Message::Text(text)) => {
-                    let attitude: Attitude = serde_json::from_str(text.as_str()).unwrap();
-
-                    // trace!(?attitude, "websocket message");
-
-                    *attitude_mutex.lock() = attitude;
-                    request_repaint();
-                }
-                Ok(_) => {}
-                Err(tungstenite::Error::ConnectionClosed) => {
-                    break;
-                }
-                Err(tungstenite::Error::Protocol(
-                    tungstenite::error::ProtocolError::ResetWithoutClosingHandshake,
-                )) => {
-                    debug!("reset without closing handshake. Likely iOS tab unfocused");
-
-                    break;
-                }
-                Err(error) => {
-                    warn!(%error, "error in websocket connection");
-                    break;
-                }
+            if config.server.enabled {
+                let ingest = ingest.clone();
+                let config = config.server.clone();
+                thread::spawn(move || ingest::websocket::websocket_thread(config, ingest));
+            }
+            if config.mavlink.enabled {
+                let ingest = ingest.clone();
+                let config = config.mavlink.clone();
+                thread::spawn(move || ingest::mavlink::mavlink_thread(config, ingest));
+            }
+            if config.udp.enabled {
+                let ingest = ingest.clone();
+                let config = config.udp.clone();
+                thread::spawn(move || ingest::udp::udp_thread(config, ingest));
+            }
+            if config.tcp.enabled {
+                let ingest = ingest.clone();
+                let config = config.tcp.clone();
+                thread::spawn(move || ingest::tcp::tcp_thread(config, ingest));
+            }
+            if config.serial.enabled {
+                let ingest = ingest.clone();
+                let config = config.serial.clone();
+                thread::spawn(move || ingest::serial::serial_thread(config, ingest));
             }
-        }
 
-        connection.store(false, Ordering::Release);
-        request_repaint();
+            let simulation = simulator::Simulation::new(ingest, config.simulator.clone());
+
+            Ok(Box::new(window::MainWindow::new(
+                vehicles,
+                recorder,
+                status,
+                replay,
+                simulation,
+                MapState::new(config.map.tiles.clone()),
+                config.diagnostics.stale_timeout(),
+            )))
+        }),
+    )?;
 
-        trace!("websocket connection closed");
-    }
+    Ok(())
 }
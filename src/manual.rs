@@ -0,0 +1,297 @@
+//! Manual input of telemetry, to work on the instruments without any hardware
+//!
+//! The panel either drives a virtual vehicle of its own, or changes the values shown for a
+//! live vehicle while it is frozen, see [`Vehicle::freeze`].
+
+use std::{ops::RangeInclusive, time::Instant};
+
+use eframe::egui::{self, emath::Numeric, Slider, SliderClamping};
+use flock_telemetry::{
+    connection::{Connection, ConnectionId},
+    telemetry::{Battery, GpsFix, Position, Telemetry},
+    vehicle::{Vehicle, VehicleId, Vehicles},
+};
+
+/// The vehicle the panel drives
+const VEHICLE: &str = "manual";
+
+/// The virtual vehicle driven by hand
+pub struct ManualInput {
+    pub enabled: bool,
+    telemetry: Telemetry,
+    /// The connection of the virtual vehicle, while enabled
+    connection: Option<ConnectionId>,
+    /// Whether the telemetry changed since it was last applied
+    changed: bool,
+}
+
+impl Default for ManualInput {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            telemetry: Telemetry {
+                heading: Some(0.0),
+                pitch: Some(0.0),
+                roll: Some(0.0),
+                altitude: Some(20.0),
+                relative_altitude: Some(0.0),
+                airspeed: Some(0.0),
+                vertical_speed: Some(0.0),
+                ..Default::default()
+            },
+            connection: None,
+            changed: true,
+        }
+    }
+}
+
+impl ManualInput {
+    pub fn id() -> VehicleId {
+        VehicleId::new(VEHICLE)
+    }
+
+    /// Connect, update or disconnect the virtual vehicle, before the vehicles are drawn
+    pub fn apply(&mut self, vehicles: &mut Vehicles) {
+        let now = Instant::now();
+        let vehicle = match (self.enabled, self.connection) {
+            (false, None) => return,
+            (false, Some(connection)) => {
+                if let Some(vehicle) = vehicles.get_mut(&Self::id()) {
+                    vehicle.connections.remove(&connection);
+                }
+                self.connection = None;
+
+                return;
+            }
+            (true, None) => {
+                let connection = ConnectionId::next();
+                let vehicle = vehicles.get_or_insert(&Self::id());
+                vehicle
+                    .connections
+                    .insert(connection, Connection::new("manual", "override panel", now));
+                self.connection = Some(connection);
+                self.changed = true;
+
+                vehicle
+            }
+            (true, Some(_)) => vehicles.get_or_insert(&Self::id()),
+        };
+        let Some(connection) = self.connection else {
+            return;
+        };
+
+        if self.changed {
+            // The panel is the whole state, so fields it leaves out disappear
+            vehicle.telemetry = Telemetry::default();
+            vehicle.update(&self.telemetry, now);
+        }
+        if let Some(connection) = vehicle.connections.get_mut(&connection) {
+            if self.changed {
+                connection.received(now, 0);
+            }
+            // Standing still is not the same as going quiet
+            connection.last_message = Some(now);
+        }
+        self.changed = false;
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        self.changed |= fields(ui, &mut self.telemetry);
+    }
+}
+
+/// A change the operator made to a live vehicle
+pub enum Override {
+    Freeze,
+    Unfreeze,
+    /// Show this instead of the frozen telemetry
+    Nudge(Telemetry),
+}
+
+/// The override panel of a live vehicle, returning what the operator changed
+pub fn override_ui(ui: &mut egui::Ui, vehicle: &Vehicle) -> Option<Override> {
+    let mut frozen = vehicle.frozen();
+    let toggled = ui
+        .checkbox(&mut frozen, "Freeze")
+        .on_hover_text("Stop following the telemetry received, to change the values shown")
+        .changed();
+
+    let mut telemetry = vehicle.telemetry.clone();
+    let nudged = vehicle.frozen() && fields(ui, &mut telemetry);
+
+    match (toggled, frozen) {
+        (true, true) => Some(Override::Freeze),
+        (true, false) => Some(Override::Unfreeze),
+        (false, _) => nudged.then_some(Override::Nudge(telemetry)),
+    }
+}
+
+/// Editors for every field of `telemetry`, returning whether any changed
+pub fn fields(ui: &mut egui::Ui, telemetry: &mut Telemetry) -> bool {
+    let mut changed = false;
+
+    egui::Grid::new("manual fields")
+        .num_columns(2)
+        .striped(true)
+        .show(ui, |ui| {
+            changed |= number(ui, "Heading", &mut telemetry.heading, 0.0..=360.0, "°");
+            changed |= number(ui, "Pitch", &mut telemetry.pitch, -90.0..=90.0, "°");
+            changed |= number(ui, "Roll", &mut telemetry.roll, -180.0..=180.0, "°");
+
+            changed |= optional(ui, "Position", &mut telemetry.position, |ui, position| {
+                let Position {
+                    latitude,
+                    longitude,
+                } = position;
+
+                ui.vertical(|ui| {
+                    slider(ui, latitude, -90.0..=90.0, "° N")
+                        | slider(ui, longitude, -180.0..=180.0, "° E")
+                })
+                .inner
+            });
+            changed |= number(
+                ui,
+                "Altitude",
+                &mut telemetry.altitude,
+                -100.0..=1000.0,
+                " m",
+            );
+            changed |= number(
+                ui,
+                "Relative altitude",
+                &mut telemetry.relative_altitude,
+                -100.0..=1000.0,
+                " m",
+            );
+
+            changed |= number(ui, "Airspeed", &mut telemetry.airspeed, 0.0..=100.0, " m/s");
+            changed |= number(
+                ui,
+                "Groundspeed",
+                &mut telemetry.groundspeed,
+                0.0..=100.0,
+                " m/s",
+            );
+            changed |= number(
+                ui,
+                "Vertical speed",
+                &mut telemetry.vertical_speed,
+                -20.0..=20.0,
+                " m/s",
+            );
+
+            let battery = telemetry.battery.get_or_insert_with(Battery::default);
+            changed |= number(
+                ui,
+                "Battery voltage",
+                &mut battery.voltage,
+                0.0..=30.0,
+                " V",
+            );
+            changed |= number(
+                ui,
+                "Battery current",
+                &mut battery.current,
+                0.0..=100.0,
+                " A",
+            );
+            changed |= number(
+                ui,
+                "Battery remaining",
+                &mut battery.remaining,
+                0.0..=100.0,
+                " %",
+            );
+            if *battery == Battery::default() {
+                telemetry.battery = None;
+            }
+
+            changed |= optional(ui, "GPS", &mut telemetry.gps, |ui, gps| {
+                let mut changed = false;
+
+                ui.horizontal(|ui| {
+                    egui::ComboBox::from_id_salt("manual gps fix")
+                        .selected_text(format!("{:?}", gps.fix))
+                        .show_ui(ui, |ui| {
+                            for fix in [
+                                GpsFix::None,
+                                GpsFix::Fix2D,
+                                GpsFix::Fix3D,
+                                GpsFix::Dgps,
+                                GpsFix::RtkFloat,
+                                GpsFix::RtkFixed,
+                            ] {
+                                changed |= ui
+                                    .selectable_value(&mut gps.fix, fix, format!("{fix:?}"))
+                                    .changed();
+                            }
+                        });
+
+                    let satellites = gps.satellites.get_or_insert(0);
+                    changed |= slider(ui, satellites, 0..=30, " satellites");
+                });
+
+                changed
+            });
+
+            changed |= optional(ui, "Flight mode", &mut telemetry.flight_mode, |ui, mode| {
+                ui.text_edit_singleline(mode).changed()
+            });
+            changed |= optional(ui, "Armed", &mut telemetry.armed, |ui, armed| {
+                ui.checkbox(armed, "").changed()
+            });
+        });
+
+    changed
+}
+
+/// A row for an optional number, a checkbox to send it at all and a slider to set it
+fn number<T: Numeric + Default>(
+    ui: &mut egui::Ui,
+    name: &str,
+    value: &mut Option<T>,
+    range: RangeInclusive<T>,
+    unit: &str,
+) -> bool {
+    optional(ui, name, value, |ui, value| slider(ui, value, range, unit))
+}
+
+/// A row for an optional field, a checkbox to send it at all and `edit` to change it
+fn optional<T: Default>(
+    ui: &mut egui::Ui,
+    name: &str,
+    value: &mut Option<T>,
+    edit: impl FnOnce(&mut egui::Ui, &mut T) -> bool,
+) -> bool {
+    let mut present = value.is_some();
+    let mut changed = ui.checkbox(&mut present, name).changed();
+
+    match (present, value.as_mut()) {
+        (true, Some(value)) => changed |= edit(ui, value),
+        (true, None) => *value = Some(T::default()),
+        (false, _) => {
+            *value = None;
+            ui.weak("not sent");
+        }
+    }
+    ui.end_row();
+
+    changed
+}
+
+/// The range only limits the slider, values outside it can still be typed in
+fn slider<T: Numeric>(
+    ui: &mut egui::Ui,
+    value: &mut T,
+    range: RangeInclusive<T>,
+    unit: &str,
+) -> bool {
+    ui.add(
+        Slider::new(value, range)
+            .suffix(unit)
+            .clamping(SliderClamping::Never)
+            .trailing_fill(true),
+    )
+    .changed()
+}
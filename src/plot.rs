@@ -0,0 +1,305 @@
+//! Strip charts of the recent telemetry history of a vehicle
+
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use eframe::egui::{self, Color32, RichText};
+use egui_plot::{Legend, Line, Plot, PlotBounds, PlotPoints};
+use flock_telemetry::{
+    telemetry::{NumericField, NUMERIC_FIELDS},
+    vehicle::{Vehicle, VehicleId, HISTORY_AGE},
+};
+
+/// Selectable widths of the live window, in seconds, up to the [`HISTORY_AGE`] every vehicle keeps
+const WINDOWS: [(f64, &str); 5] = [
+    (10.0, "10 s"),
+    (30.0, "30 s"),
+    (60.0, "1 min"),
+    (300.0, "5 min"),
+    (HISTORY_AGE.as_secs_f64(), "30 min"),
+];
+
+/// How often live plots scroll when no telemetry arrives
+const LIVE_REPAINT: Duration = Duration::from_millis(100);
+
+pub struct Plots {
+    /// Seconds of history shown while live
+    window: f64,
+    /// When the plots were frozen, live when `None`
+    paused_at: Option<Instant>,
+    /// Seconds shown along the x axis last frame, what to draw while paused
+    shown_range: Option<(f64, f64)>,
+    /// Whether each of [`NUMERIC_FIELDS`] is plotted
+    shown: Vec<bool>,
+    /// Outcome of the last CSV export
+    export: Option<Result<PathBuf, String>>,
+}
+
+impl Default for Plots {
+    fn default() -> Self {
+        Self {
+            window: 30.0,
+            paused_at: None,
+            shown_range: None,
+            shown: NUMERIC_FIELDS
+                .iter()
+                .map(|field| matches!(field.name, "heading" | "pitch" | "roll"))
+                .collect(),
+            export: None,
+        }
+    }
+}
+
+impl Plots {
+    pub fn ui(&mut self, ui: &mut egui::Ui, id: &VehicleId, vehicle: &Vehicle) {
+        let live = self.paused_at.is_none();
+        let end = self.paused_at.unwrap_or_else(Instant::now);
+        // Seconds relative to the right edge of the live window
+        let seconds = |time: Instant| {
+            if time <= end {
+                -(end - time).as_secs_f64()
+            } else {
+                (time - end).as_secs_f64()
+            }
+        };
+        // Index of the first update later than `after`, the history being oldest first
+        let index_after = |after: f64| {
+            vehicle
+                .history
+                .partition_point(|(time, _)| seconds(*time) <= after)
+        };
+
+        let mut export = false;
+
+        ui.horizontal(|ui| {
+            for (window, label) in WINDOWS {
+                ui.selectable_value(&mut self.window, window, label);
+            }
+            ui.separator();
+
+            if ui
+                .button(if live { "Pause" } else { "Resume" })
+                .on_hover_text("While paused, drag to pan and scroll to zoom")
+                .clicked()
+            {
+                self.paused_at = if live { Some(Instant::now()) } else { None };
+            }
+
+            export = ui
+                .button("Export CSV")
+                .on_hover_text("Save the visible range")
+                .clicked();
+
+            match &self.export {
+                Some(Ok(path)) => {
+                    ui.label(format!("Exported to {}", path.display()));
+                }
+                Some(Err(error)) => {
+                    ui.label(RichText::new(error).color(Color32::RED));
+                }
+                None => {}
+            }
+        });
+
+        ui.horizontal_wrapped(|ui| {
+            for (field, shown) in NUMERIC_FIELDS.iter().zip(&mut self.shown) {
+                ui.checkbox(shown, field.name);
+            }
+        });
+
+        let fields = NUMERIC_FIELDS
+            .iter()
+            .zip(&self.shown)
+            .filter(|(_, shown)| **shown)
+            .map(|(field, _)| field)
+            .collect::<Vec<_>>();
+
+        // Only the updates on screen, and one on either side so the lines reach the edges. While
+        // paused, half a screen more on either side, for panning before the next frame catches up
+        let (from, to) = match self.shown_range {
+            Some((min, max)) if !live => (min - (max - min) / 2.0, max + (max - min) / 2.0),
+            _ => (-self.window, 0.0),
+        };
+        let visible =
+            index_after(from).saturating_sub(1)..(index_after(to) + 1).min(vehicle.history.len());
+
+        let series = fields
+            .iter()
+            .map(|field| {
+                let points = vehicle
+                    .history
+                    .range(visible.clone())
+                    .filter_map(|(time, telemetry)| {
+                        Some([seconds(*time), (field.value)(telemetry)?])
+                    })
+                    .collect::<Vec<_>>();
+
+                (*field, points)
+            })
+            .collect::<Vec<_>>();
+
+        let window = self.window;
+        let response = Plot::new(("plots", id.as_str()))
+            .legend(Legend::default())
+            .x_axis_label("s")
+            .allow_drag(!live)
+            .allow_zoom(!live)
+            .allow_scroll(!live)
+            .allow_boxed_zoom(!live)
+            .height(ui.available_height() - ui.spacing().interact_size.y * 2.0)
+            .show(ui, |plot_ui| {
+                if live {
+                    let (min, max) = series
+                        .iter()
+                        .flat_map(|(_, points)| points)
+                        .filter(|[x, _]| *x >= -window)
+                        .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), [_, y]| {
+                            (min.min(*y), max.max(*y))
+                        });
+                    let (min, max) = if min <= max {
+                        let margin = ((max - min) * 0.05).max(0.5);
+                        (min - margin, max + margin)
+                    } else {
+                        (-1.0, 1.0)
+                    };
+
+                    plot_ui.set_plot_bounds(PlotBounds::from_min_max([-window, min], [0.0, max]));
+                }
+
+                for (field, points) in series {
+                    plot_ui.line(Line::new(PlotPoints::from(points)).name(label(field)));
+                }
+
+                plot_ui.pointer_coordinate()
+            });
+
+        // Cursor readout, the latest value of every field at the time under the cursor
+        ui.horizontal_wrapped(|ui| {
+            let Some(pointer) = response.inner else {
+                ui.label("Hover the plot for a readout");
+                return;
+            };
+
+            ui.monospace(format!("t = {:+.2} s", pointer.x));
+
+            let before_pointer = index_after(pointer.x);
+            for field in &fields {
+                let value = vehicle
+                    .history
+                    .range(..before_pointer)
+                    .rev()
+                    .find_map(|(_, telemetry)| (field.value)(telemetry));
+
+                if let Some(value) = value {
+                    ui.separator();
+                    ui.monospace(format!("{}: {value:.2}{}", field.name, field.unit));
+                }
+            }
+        });
+
+        let bounds = response.transform.bounds();
+        self.shown_range = Some((bounds.min()[0], bounds.max()[0]));
+
+        if export {
+            let range = bounds.min()[0]..=bounds.max()[0];
+            let path = default_export_path(id);
+
+            self.export = Some(
+                export_csv(&path, vehicle, &fields, |time| {
+                    range.contains(&seconds(time))
+                })
+                .map(|()| path.clone())
+                .map_err(|error| format!("Failed to export to {}: {error}", path.display())),
+            );
+        }
+
+        if live {
+            ui.ctx().request_repaint_after(LIVE_REPAINT);
+        }
+    }
+}
+
+fn label(field: &NumericField) -> String {
+    if field.unit.is_empty() {
+        field.name.to_owned()
+    } else {
+        format!("{} ({})", field.name, field.unit)
+    }
+}
+
+/// A file name for an export of `id`, unique per second
+fn default_export_path(id: &VehicleId) -> PathBuf {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let id = id
+        .as_str()
+        .chars()
+        .map(|char| {
+            if char.is_ascii_alphanumeric() {
+                char
+            } else {
+                '_'
+            }
+        })
+        .collect::<String>();
+
+    PathBuf::from(format!("flock-{id}-{timestamp}.csv"))
+}
+
+/// Write every update in the history of `vehicle` that `include`s its time and carries one of
+/// `fields`, with its time as seconds since the unix epoch
+fn export_csv(
+    path: &Path,
+    vehicle: &Vehicle,
+    fields: &[&NumericField],
+    include: impl Fn(Instant) -> bool,
+) -> io::Result<()> {
+    let mut file = BufWriter::new(File::create(path)?);
+
+    let now = Instant::now();
+    let unix_now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs_f64();
+
+    write!(file, "unix_time")?;
+    for field in fields {
+        write!(file, ",{}", field.name)?;
+    }
+    writeln!(file)?;
+
+    for (time, telemetry) in &vehicle.history {
+        if !include(*time) {
+            continue;
+        }
+
+        let values = fields
+            .iter()
+            .map(|field| (field.value)(telemetry))
+            .collect::<Vec<_>>();
+        if values.iter().all(Option::is_none) {
+            continue;
+        }
+
+        write!(
+            file,
+            "{:.3}",
+            unix_now - now.saturating_duration_since(*time).as_secs_f64()
+        )?;
+        for value in values {
+            match value {
+                Some(value) => write!(file, ",{value}")?,
+                None => write!(file, ",")?,
+            }
+        }
+        writeln!(file)?;
+    }
+
+    file.flush()
+}
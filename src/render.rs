@@ -0,0 +1,243 @@
+//! Headless rendering of the instruments to PNG images, for flight reports and video overlays
+//!
+//! Renders either a single telemetry message to one image, or a recording to a directory of
+//! numbered frames at a fixed frame rate, which tools like ffmpeg turn into a video.
+
+use std::{
+    fs, io,
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+use eframe::egui::Frame;
+use flock_instruments::{
+    AttitudeIndicator, AttitudeIndicatorRectangular, HeadingIndicator, HeadlessRenderer, MapState,
+    PrimaryFlightDisplay, Validity,
+};
+use flock_telemetry::{
+    calibration::Calibrations,
+    recording::Replay,
+    telemetry::{Telemetry, TelemetryError},
+    vehicle::{VehicleId, Vehicles},
+};
+use tracing::{info, warn};
+
+use crate::{config::Config, window};
+
+/// Longest to wait for the map tiles of a frame to load
+const TILE_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, clap::Args)]
+pub struct RenderArgs {
+    /// A single JSON telemetry message to render to one image
+    #[arg(
+        long,
+        required_unless_present = "recording",
+        conflicts_with = "recording"
+    )]
+    pub sample: Option<PathBuf>,
+
+    /// A recording to render frame by frame
+    #[arg(long)]
+    pub recording: Option<PathBuf>,
+
+    /// Vehicle of the recording to render [default: the first]
+    #[arg(long, requires = "recording")]
+    pub vehicle: Option<String>,
+
+    /// PNG file for a sample, directory of `frame-NNNNNN.png` files for a recording
+    #[arg(long, short)]
+    pub output: PathBuf,
+
+    #[arg(long, value_enum, default_value_t = Layout::Instruments)]
+    pub layout: Layout,
+
+    /// Width of the images in pixels
+    #[arg(long, default_value_t = 1200)]
+    pub width: u32,
+
+    /// Height of the images in pixels
+    #[arg(long, default_value_t = 400)]
+    pub height: u32,
+
+    /// Pixels per point, to scale up the instruments
+    #[arg(long, default_value_t = 1.0)]
+    pub scale: f32,
+
+    /// Frames per second of recording time
+    #[arg(long, default_value_t = 30.0, value_parser = positive)]
+    pub fps: f64,
+}
+
+fn positive(text: &str) -> Result<f64, String> {
+    match text.parse::<f64>() {
+        Ok(value) if value > 0.0 && value.is_finite() => Ok(value),
+        Ok(_) => Err("must be a positive number".to_owned()),
+        Err(error) => Err(error.to_string()),
+    }
+}
+
+/// What to draw in the image, the views of the main window and single instruments
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Layout {
+    /// The individual instruments side by side
+    Instruments,
+    Attitude,
+    AttitudeRectangular,
+    Heading,
+    /// Primary flight display
+    Pfd,
+    /// Every vehicle with a known position
+    Map,
+}
+
+#[derive(Debug)]
+pub enum RenderError {
+    Read(PathBuf, io::Error),
+    Parse(PathBuf, TelemetryError),
+    /// The recording has no vehicle of this name, or no vehicles at all
+    UnknownVehicle(Option<String>),
+    Write(PathBuf, image::ImageError),
+}
+
+impl std::fmt::Display for RenderError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RenderError::Read(path, error) => {
+                write!(f, "failed to read {}: {error}", path.display())
+            }
+            RenderError::Parse(path, error) => {
+                write!(f, "failed to parse {}: {error}", path.display())
+            }
+            RenderError::UnknownVehicle(Some(id)) => write!(f, "no vehicle {id} in the recording"),
+            RenderError::UnknownVehicle(None) => f.write_str("the recording is empty"),
+            RenderError::Write(path, error) => {
+                write!(f, "failed to write {}: {error}", path.display())
+            }
+        }
+    }
+}
+
+impl std::error::Error for RenderError {}
+
+/// Render what `args` asks for, with the map tiles, calibrations and stale timeout of `config`
+pub fn run(args: RenderArgs, config: &Config) -> Result<(), RenderError> {
+    let calibrations = Calibrations::load(&config.calibration.path).unwrap_or_else(|error| {
+        warn!(%error, "rendering without calibrations");
+        Calibrations::default()
+    });
+    let (mut vehicles, _updates) = Vehicles::new(calibrations);
+    let mut renderer = HeadlessRenderer::new();
+    let mut map = MapState::new(config.map.tiles.clone());
+    let stale_timeout = config.diagnostics.stale_timeout();
+
+    let mut render = |vehicles: &Vehicles, id: &VehicleId, validity: Validity, path: PathBuf| {
+        let vehicle = &vehicles[id];
+
+        // Map tiles load in the background, draw again until the ones on screen arrived
+        let image = loop {
+            let image = renderer.render([args.width, args.height], args.scale, |ui| {
+                let telemetry = &vehicle.telemetry;
+                let ((pitch, roll), attitude) =
+                    window::reported(telemetry.pitch.zip(telemetry.roll), validity);
+                let (heading, heading_validity) = window::reported(telemetry.heading, validity);
+                let canvas = Frame::canvas(ui.style());
+
+                match args.layout {
+                    Layout::Instruments => window::instruments(ui, telemetry, validity),
+                    Layout::Attitude => {
+                        canvas.show(ui, |ui| {
+                            ui.add(AttitudeIndicator::new(pitch, roll).validity(attitude))
+                        });
+                    }
+                    Layout::AttitudeRectangular => {
+                        canvas.show(ui, |ui| {
+                            ui.add(
+                                AttitudeIndicatorRectangular::new(pitch, roll).validity(attitude),
+                            )
+                        });
+                    }
+                    Layout::Heading => {
+                        canvas.show(ui, |ui| {
+                            ui.add(HeadingIndicator::new(heading).validity(heading_validity))
+                        });
+                    }
+                    Layout::Pfd => {
+                        canvas.show(ui, |ui| {
+                            ui.add(PrimaryFlightDisplay::new(telemetry).validity(validity))
+                        });
+                    }
+                    Layout::Map => window::map(ui, &mut map, vehicles, id),
+                }
+            });
+
+            if !map.wait_for_tiles(renderer.context(), TILE_TIMEOUT) {
+                break image;
+            }
+        };
+
+        image
+            .save(&path)
+            .map_err(|error| RenderError::Write(path, error))
+    };
+
+    if let Some(path) = &args.sample {
+        let text =
+            fs::read_to_string(path).map_err(|error| RenderError::Read(path.clone(), error))?;
+        let telemetry =
+            Telemetry::from_json(&text).map_err(|error| RenderError::Parse(path.clone(), error))?;
+
+        let id = VehicleId::new("sample");
+        vehicles
+            .get_or_insert(&id)
+            .update(&telemetry, Instant::now());
+        render(&vehicles, &id, Validity::Valid, args.output.clone())?;
+        info!(path = %args.output.display(), "rendered sample");
+
+        return Ok(());
+    }
+
+    let Some(path) = &args.recording else {
+        return Ok(());
+    };
+    let mut replay = Replay::open(path).map_err(|error| RenderError::Read(path.clone(), error))?;
+    replay.set_connected(&mut vehicles, true);
+
+    let id = match &args.vehicle {
+        Some(id) => VehicleId::new(id.as_str()),
+        None => vehicles
+            .keys()
+            .next()
+            .cloned()
+            .ok_or(RenderError::UnknownVehicle(None))?,
+    };
+    if !vehicles.contains_key(&id) {
+        return Err(RenderError::UnknownVehicle(args.vehicle.clone()));
+    }
+
+    fs::create_dir_all(&args.output).map_err(|error| {
+        RenderError::Write(args.output.clone(), image::ImageError::IoError(error))
+    })?;
+
+    let frames = (replay.duration() * args.fps).floor() as usize + 1;
+    for frame in 0..frames {
+        replay.advance_to(frame as f64 / args.fps, &mut vehicles);
+
+        // Gaps in the recording show up as stale, like they did live, measured in recording
+        // time so the frames do not depend on how fast they are rendered
+        let age = replay
+            .last_sample_time(&id)
+            .map(|time| Duration::from_secs_f64(replay.position() - time));
+        let validity = match age {
+            None => Validity::Disconnected,
+            Some(age) if age > stale_timeout => Validity::Stale(age),
+            Some(_) => Validity::Valid,
+        };
+        let path = args.output.join(format!("frame-{frame:06}.png"));
+
+        render(&vehicles, &id, validity, path)?;
+    }
+    info!(frames, directory = %args.output.display(), "rendered recording");
+
+    Ok(())
+}
@@ -0,0 +1,68 @@
+//! The simulator menu, starting and stopping [`flock_telemetry::ingest::simulator`] vehicles
+
+use eframe::egui::{self, DragValue, Slider};
+use flock_telemetry::{
+    config::{Scenario, SimulatorConfig},
+    ingest::{simulator::Simulator, Ingest},
+};
+
+/// The simulated vehicles, and the settings the next ones are started with
+pub struct Simulation {
+    ingest: Ingest,
+    config: SimulatorConfig,
+    simulator: Option<Simulator>,
+}
+
+impl Simulation {
+    /// Starts right away when `config` is enabled
+    pub fn new(ingest: Ingest, config: SimulatorConfig) -> Self {
+        let simulator = config
+            .enabled
+            .then(|| Simulator::start(config.clone(), ingest.clone()));
+
+        Self {
+            ingest,
+            config,
+            simulator,
+        }
+    }
+
+    pub fn running(&self) -> bool {
+        self.simulator.is_some()
+    }
+
+    pub fn menu(&mut self, ui: &mut egui::Ui) {
+        let running = self.running();
+
+        ui.add_enabled_ui(!running, |ui| {
+            let config = &mut self.config;
+
+            ui.horizontal(|ui| {
+                ui.label("Vehicles");
+                ui.add(DragValue::new(&mut config.vehicles).range(1..=50));
+            });
+            ui.horizontal(|ui| {
+                ui.selectable_value(&mut config.scenario, Scenario::Scripted, "Scripted")
+                    .on_hover_text("Turns, climbs, rolls and loops in a fixed order");
+                ui.selectable_value(&mut config.scenario, Scenario::Random, "Random")
+                    .on_hover_text("Manoeuvres picked at random");
+            });
+            ui.add(Slider::new(&mut config.rate, 1.0..=50.0).text("Hz"));
+            ui.add(Slider::new(&mut config.noise, 0.0..=5.0).text("° noise"));
+            ui.add(Slider::new(&mut config.dropouts, 0.0..=10.0).text("dropouts / min"));
+        });
+
+        ui.separator();
+
+        if running {
+            if ui.button("Stop simulator").clicked() {
+                // Joins the thread, after which its vehicles are disconnected
+                self.simulator = None;
+                ui.close_menu();
+            }
+        } else if ui.button("Start simulator").clicked() {
+            self.simulator = Some(Simulator::start(self.config.clone(), self.ingest.clone()));
+            ui.close_menu();
+        }
+    }
+}
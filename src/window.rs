@@ -1,100 +1,538 @@
-use std::sync::{
-    atomic::{AtomicBool, Ordering},
-    Arc,
-};
+use std::time::{Duration, Instant};
 
-use eframe::egui::{self, mutex::Mutex, Color32, Frame, RichText};
+use eframe::egui::{self, Color32, Frame, RichText, Slider};
+use flock_instruments::{
+    AttitudeIndicator, AttitudeIndicatorRectangular, HeadingIndicator, Map, MapMarker, MapState,
+    PrimaryFlightDisplay, QrCode, Validity,
+};
+use flock_telemetry::{
+    command::Command,
+    connection::ConnectionId,
+    recording::{default_recording_path, Recorder, Replay},
+    status::{ListenerStatus, Status},
+    telemetry::Telemetry,
+    vehicle::{VehicleId, Vehicles},
+};
 
-use crate::component::{
-    attitude::{AttitudeIndicator, AttitudeIndicatorRectangular},
-    heading::HeadingIndicator,
+use crate::{
+    calibration,
+    command::Commands,
+    diagnostics,
+    manual::{self, ManualInput, Override},
+    plot::Plots,
+    simulator::Simulation,
 };
 
-pub struct MainWindow {
-    attitude: Arc<Mutex<Attitude>>,
-    connection: Arc<AtomicBool>,
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum View {
+    /// The individual instruments side by side
+    Instruments,
+    PrimaryFlightDisplay,
+    /// Every vehicle with a known position on a map
+    Map,
+    /// History of the selected vehicle over time
+    Plots,
+    /// Statistics of every open connection
+    Connections,
 }
 
-#[derive(Debug, Default, Clone, Copy, serde::Deserialize)]
-pub struct Attitude {
-    pub heading: f32,
-    pub pitch: f32,
-    pub roll: f32,
+pub struct MainWindow {
+    vehicles: Vehicles,
+    selected: Option<VehicleId>,
+    view: View,
+    recorder: Recorder,
+    status: Status,
+    replay: Option<Replay>,
+    simulation: Simulation,
+    manual: ManualInput,
+    map: MapState,
+    plots: Plots,
+    commands: Commands,
+    /// How long a connected vehicle may go without telemetry before it is flagged
+    stale_timeout: Duration,
+    /// Contents of the "Open replay" path field in the file menu
+    replay_path: String,
+    /// Last error from a file menu action
+    file_error: Option<String>,
 }
 
 impl MainWindow {
-    pub fn new(attitude: Arc<Mutex<Attitude>>, connection: Arc<AtomicBool>) -> Self {
+    pub fn new(
+        mut vehicles: Vehicles,
+        recorder: Recorder,
+        status: Status,
+        replay: Option<Replay>,
+        simulation: Simulation,
+        map: MapState,
+        stale_timeout: Duration,
+    ) -> Self {
+        if let Some(replay) = &replay {
+            replay.set_connected(&mut vehicles, true);
+        }
+
         Self {
-            attitude,
-            connection,
+            vehicles,
+            selected: None,
+            view: View::Instruments,
+            recorder,
+            status,
+            replay,
+            simulation,
+            manual: ManualInput::default(),
+            map,
+            plots: Plots::default(),
+            commands: Commands::default(),
+            stale_timeout,
+            replay_path: String::new(),
+            file_error: None,
+        }
+    }
+
+    fn open_replay(&mut self, path: &str) {
+        self.close_replay();
+
+        match Replay::open(path) {
+            Ok(replay) => {
+                replay.set_connected(&mut self.vehicles, true);
+                self.replay = Some(replay);
+                self.file_error = None;
+            }
+            Err(error) => self.file_error = Some(format!("Failed to open {path}: {error}")),
+        }
+    }
+
+    fn close_replay(&mut self) {
+        if let Some(replay) = self.replay.take() {
+            replay.set_connected(&mut self.vehicles, false);
+        }
+    }
+
+    fn file_menu(&mut self, ui: &mut egui::Ui) {
+        if let Some(path) = self.recorder.path() {
+            if ui
+                .button(format!("Stop recording {}", path.display()))
+                .clicked()
+            {
+                self.recorder.stop();
+                ui.close_menu();
+            }
+        } else if ui.button("Start recording").clicked() {
+            let path = default_recording_path();
+
+            self.file_error = self
+                .recorder
+                .start(&path)
+                .err()
+                .map(|error| format!("Failed to record to {}: {error}", path.display()));
+            ui.close_menu();
+        }
+
+        ui.separator();
+
+        ui.horizontal(|ui| {
+            ui.text_edit_singleline(&mut self.replay_path)
+                .on_hover_text("Path of a recording to replay");
+
+            if ui.button("Open replay").clicked() {
+                let path = self.replay_path.clone();
+                self.open_replay(&path);
+                ui.close_menu();
+            }
+        });
+
+        if self.replay.is_some() && ui.button("Close replay").clicked() {
+            self.close_replay();
+            ui.close_menu();
+        }
+    }
+
+    fn status_bar(&self, ctx: &egui::Context) {
+        let status = self.status.lock();
+
+        egui::TopBottomPanel::bottom("status").show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                for (index, (name, listener)) in status.listeners.iter().enumerate() {
+                    if index > 0 {
+                        ui.separator();
+                    }
+                    listener_status(ui, name, listener);
+                }
+            });
+        });
+    }
+
+    fn replay_controls(&mut self, ctx: &egui::Context) {
+        let Some(replay) = &mut self.replay else {
+            return;
+        };
+
+        // Clamp so resuming after a long idle period does not skip ahead
+        let elapsed = Duration::from_secs_f32(ctx.input(|input| input.unstable_dt).min(0.1));
+        replay.advance(elapsed, &mut self.vehicles);
+
+        if !replay.paused && !replay.finished() {
+            ctx.request_repaint();
+        }
+
+        let mut close = false;
+
+        egui::TopBottomPanel::bottom("replay").show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                ui.label(format!("Replay {}", replay.path().display()));
+                if replay.skipped() > 0 {
+                    ui.label(
+                        RichText::new(format!("{} invalid lines skipped", replay.skipped()))
+                            .color(Color32::YELLOW),
+                    )
+                    .on_hover_text("Likely cut off when the recording was interrupted");
+                }
+
+                if ui
+                    .button(if replay.paused { "Play" } else { "Pause" })
+                    .clicked()
+                {
+                    replay.paused = !replay.paused;
+                }
+
+                for speed in [0.5, 1.0, 2.0] {
+                    ui.selectable_value(&mut replay.speed, speed, format!("{speed}x"));
+                }
+
+                close = ui.button("Close").clicked();
+            });
+
+            let mut position = replay.position();
+            let duration = replay.duration();
+
+            ui.spacing_mut().slider_width = ui.available_width() - 100.0;
+            let scrubber = ui.add(
+                Slider::new(&mut position, 0.0..=duration)
+                    .custom_formatter(|seconds, _| format!("{seconds:.1}s / {duration:.1}s"))
+                    .trailing_fill(true),
+            );
+
+            if scrubber.changed() {
+                replay.seek(position, &mut self.vehicles);
+            }
+        });
+
+        if close {
+            self.close_replay();
         }
     }
 }
 
 impl eframe::App for MainWindow {
-    fn update(&mut self, ctx: &egui::Context, frame: &mut eframe::Frame) {
-        // egui::TopBottomPanel::top("menu").show(ctx, |ui| {
-        //     ui.horizontal(|ui| {
-        //         egui::widgets::global_theme_preference_switch(ui);
-        //         ui.menu_button("File", |ui| {});
-        //         ui.menu_button("Edit", |ui| {});
-        //         ui.menu_button("View", |ui| {});
-        //         ui.menu_button("Tools", |ui| {});
-        //         ui.menu_button("Help", |ui| {});
-        //     })
-        // });
-
-        // FIXME: Am i holding this lock for too long?
-        // TODO: how to push updates?
-        let attitude = self.attitude.lock();
+    fn on_exit(&mut self, _gl: Option<&eframe::glow::Context>) {
+        // The ingest threads keep the recorder alive, so it is never dropped to flush the tail
+        self.recorder.stop();
+    }
+
+    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        egui::TopBottomPanel::top("menu").show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                egui::widgets::global_theme_preference_switch(ui);
+                ui.menu_button("File", |ui| self.file_menu(ui));
+                ui.menu_button("Simulator", |ui| self.simulation.menu(ui));
+
+                if self.recorder.path().is_some() {
+                    ui.label(RichText::new("● REC").color(Color32::RED));
+                }
+                if self.simulation.running() {
+                    ui.label(RichText::new("SIM").color(Color32::LIGHT_BLUE));
+                }
+
+                if let Some(error) = &self.file_error {
+                    ui.label(RichText::new(error).color(Color32::RED));
+                }
+            })
+        });
+
+        self.vehicles.drain();
+
+        self.status_bar(ctx);
+        self.replay_controls(ctx);
+        self.manual.apply(&mut self.vehicles);
+
+        let vehicles = &self.vehicles;
+        let now = Instant::now();
+
+        // Keep checking for vehicles going stale while nothing arrives
+        ctx.request_repaint_after(self.stale_timeout);
+
+        // Follow the first vehicle to connect until the user picks one
+        if self.selected.is_none() {
+            self.selected = vehicles.keys().next().cloned();
+        }
+
+        egui::SidePanel::left("vehicles").show(ctx, |ui| {
+            ui.heading("Flock");
+
+            if vehicles.is_empty() {
+                ui.label("No vehicles connected");
+            }
+
+            for (id, vehicle) in vehicles.iter() {
+                let stale = vehicle.is_stale(now, self.stale_timeout);
+                let text = RichText::new(id.as_str()).color(if stale {
+                    Color32::YELLOW
+                } else if vehicle.connected() {
+                    Color32::GREEN
+                } else {
+                    Color32::RED
+                });
+
+                let label = ui.selectable_label(self.selected.as_ref() == Some(id), text);
+                let label = if stale {
+                    label.on_hover_text("Connected, but no recent telemetry")
+                } else {
+                    label
+                };
+
+                if label.clicked() {
+                    self.selected = Some(id.clone());
+                }
+            }
+
+            ui.separator();
+            if ui
+                .checkbox(&mut self.manual.enabled, "Manual vehicle")
+                .on_hover_text("A vehicle driven by the manual override panel")
+                .changed()
+                && self.manual.enabled
+            {
+                self.selected = Some(ManualInput::id());
+            }
+
+            if let Some(url) = self.status.lock().phone_page.clone() {
+                ui.separator();
+
+                egui::CollapsingHeader::new("Connect a phone")
+                    .default_open(true)
+                    .show(ui, |ui| {
+                        ui.add(QrCode::new(&url, ui.available_width().min(200.0)));
+                        ui.hyperlink(&url);
+                    });
+            }
+        });
+
+        // Applied once the vehicles are no longer borrowed for drawing
+        let mut command: Option<(VehicleId, ConnectionId, Command)> = None;
+        let mut calibration: Option<(VehicleId, calibration::Edit)> = None;
+        let mut changed: Option<(VehicleId, Override)> = None;
 
         egui::CentralPanel::default().show(ctx, |ui| {
+            let Some((id, vehicle)) = self
+                .selected
+                .as_ref()
+                .and_then(|id| vehicles.get_key_value(id))
+            else {
+                ui.label("Waiting for a vehicle to connect");
+                return;
+            };
+            let telemetry = &vehicle.telemetry;
+            let validity = match vehicle.age(now) {
+                None => Validity::Disconnected,
+                Some(age) if age > self.stale_timeout => Validity::Stale(age),
+                Some(_) => Validity::Valid,
+            };
+            if let Validity::Stale(_) = validity {
+                // Keep the age on the flag counting
+                ctx.request_repaint_after(Duration::from_secs(1));
+            }
             ui.horizontal(|ui| {
-                ui.label("Websocket status: ");
-                ui.label(if self.connection.load(Ordering::Acquire) {
+                ui.selectable_value(&mut self.view, View::Instruments, "Instruments");
+                ui.selectable_value(&mut self.view, View::PrimaryFlightDisplay, "PFD");
+                ui.selectable_value(&mut self.view, View::Map, "Map");
+                ui.selectable_value(&mut self.view, View::Plots, "Plots");
+                ui.selectable_value(&mut self.view, View::Connections, "Connections");
+                ui.separator();
+
+                ui.label(format!("{id} status: "));
+                ui.label(if vehicle.is_stale(now, self.stale_timeout) {
+                    RichText::new("connected, no recent telemetry").color(Color32::YELLOW)
+                } else if vehicle.connected() {
                     RichText::new("connected").color(Color32::GREEN)
                 } else {
                     RichText::new("disconnected").color(Color32::RED)
                 });
+
+                if vehicle.frozen() {
+                    ui.separator();
+                    ui.label(RichText::new("frozen").color(Color32::LIGHT_BLUE))
+                        .on_hover_text("Showing the values of the manual override panel");
+                }
+
+                if let Some(error) = &vehicle.last_parse_error {
+                    ui.separator();
+                    ui.label(
+                        RichText::new(format!("{} parse errors", vehicle.parse_errors))
+                            .color(Color32::YELLOW),
+                    )
+                    .on_hover_text(format!("{}\n\n{}", error.error, error.payload));
+                }
             });
 
-            // ui.add(
-            //     Slider::new(&mut attitude.heading, -360.0..=720.0)
-            //         .text("Heading")
-            //         .integer()
-            //         .step_by(5.0)
-            //         .trailing_fill(true),
-            // );
-            // ui.add(
-            //     Slider::new(&mut attitude.roll, -360.0..=360.0)
-            //         .text("Roll")
-            //         .integer()
-            //         .step_by(1.0)
-            //         .trailing_fill(true),
-            // );
-            // ui.add(
-            //     Slider::new(&mut attitude.pitch, -360.0..=360.0)
-            //         .text("Pitch")
-            //         .integer()
-            //         .step_by(1.0)
-            //         .trailing_fill(true),
-            // );
-
-            ui.columns_const(|[one, two, three]| {
-                Frame::canvas(&ctx.style()).show(one, |ui| {
-                    ui.add(AttitudeIndicator::new(attitude.pitch, attitude.roll));
+            egui::CollapsingHeader::new("Telemetry").show(ui, |ui| {
+                egui::Grid::new("telemetry").striped(true).show(ui, |ui| {
+                    for (name, value) in telemetry.fields() {
+                        ui.label(name);
+                        ui.monospace(value);
+                        ui.end_row();
+                    }
                 });
+            });
 
-                Frame::canvas(&ctx.style()).show(two, |ui| {
-                    ui.add(AttitudeIndicatorRectangular::new(
-                        attitude.pitch,
-                        attitude.roll,
-                    ));
-                });
+            egui::CollapsingHeader::new("Commands").show(ui, |ui| {
+                if let Some((connection, sent)) = self.commands.ui(ui, vehicle) {
+                    command = Some((id.clone(), connection, sent));
+                }
+            });
 
-                Frame::canvas(&ctx.style())
-                    .show(three, |ui| ui.add(HeadingIndicator::new(attitude.heading)));
+            egui::CollapsingHeader::new("Calibration").show(ui, |ui| {
+                if let Some(changed) = calibration::ui(ui, vehicle) {
+                    calibration = Some((id.clone(), changed));
+                }
             });
+
+            egui::CollapsingHeader::new("Manual override").show(ui, |ui| {
+                if *id == ManualInput::id() && self.manual.enabled {
+                    self.manual.ui(ui);
+                } else if let Some(change) = manual::override_ui(ui, vehicle) {
+                    changed = Some((id.clone(), change));
+                }
+            });
+
+            if self.view == View::Connections {
+                diagnostics::ui(ui, vehicles, self.stale_timeout);
+
+                return;
+            }
+
+            if self.view == View::Plots {
+                self.plots.ui(ui, id, vehicle);
+
+                return;
+            }
+
+            if self.view == View::Map {
+                map(ui, &mut self.map, vehicles, id);
+
+                return;
+            }
+
+            if self.view == View::PrimaryFlightDisplay {
+                Frame::canvas(&ctx.style()).show(ui, |ui| {
+                    ui.add(PrimaryFlightDisplay::new(telemetry).validity(validity));
+                });
+
+                return;
+            }
+
+            instruments(ui, telemetry, validity);
+        });
+
+        if let Some((id, edit)) = calibration {
+            // Applied live, but only written once an edit is finished, not every frame of a drag
+            self.vehicles.set_calibration(&id, edit.calibration);
+
+            if edit.finished {
+                if let Err(error) = self.vehicles.save_calibrations() {
+                    self.file_error = Some(error.to_string());
+                }
+            }
+        }
+        if let Some((id, change)) = changed {
+            if let Some(vehicle) = self.vehicles.get_mut(&id) {
+                match change {
+                    Override::Freeze => vehicle.freeze(),
+                    Override::Unfreeze => vehicle.unfreeze(),
+                    Override::Nudge(telemetry) => vehicle.telemetry = telemetry,
+                }
+            }
+        }
+        if let Some((id, connection, command)) = command {
+            if let Some(vehicle) = self.vehicles.get_mut(&id) {
+                vehicle.send_command(connection, command);
+            }
+        }
+    }
+}
+
+/// Every vehicle with a known position on a map, with `selected` highlighted
+pub fn map(ui: &mut egui::Ui, state: &mut MapState, vehicles: &Vehicles, selected: &VehicleId) {
+    let markers = vehicles
+        .iter()
+        .filter_map(|(id, vehicle)| {
+            Some(MapMarker {
+                label: id.as_str(),
+                position: vehicle.telemetry.position?,
+                heading: vehicle.telemetry.heading,
+                track: &vehicle.track,
+                selected: id == selected,
+            })
+        })
+        .collect();
+
+    Frame::canvas(ui.style()).show(ui, |ui| {
+        ui.add(Map::new(state, markers));
+    });
+}
+
+/// The individual instruments side by side
+pub fn instruments(ui: &mut egui::Ui, telemetry: &Telemetry, validity: Validity) {
+    let ((pitch, roll), attitude) = reported(telemetry.pitch.zip(telemetry.roll), validity);
+    let (heading, heading_validity) = reported(telemetry.heading, validity);
+    let style = ui.style().clone();
+
+    ui.columns_const(|[one, two, three]| {
+        Frame::canvas(&style).show(one, |ui| {
+            ui.add(AttitudeIndicator::new(pitch, roll).validity(attitude));
+        });
+
+        Frame::canvas(&style).show(two, |ui| {
+            ui.add(AttitudeIndicatorRectangular::new(pitch, roll).validity(attitude));
+        });
+
+        Frame::canvas(&style).show(three, |ui| {
+            ui.add(HeadingIndicator::new(heading).validity(heading_validity))
         });
+    });
+}
+
+/// The value an instrument shows and its validity, flagged as missing if it was never reported
+pub fn reported<T: Default>(value: Option<T>, validity: Validity) -> (T, Validity) {
+    match value {
+        Some(value) => (value, validity),
+        None => (T::default(), Validity::Missing),
+    }
+}
+
+fn listener_status(ui: &mut egui::Ui, name: &str, status: &ListenerStatus) {
+    if let Some(error) = &status.config_error {
+        ui.label(RichText::new(format!("{name}: invalid config")).color(Color32::RED))
+            .on_hover_text(error);
+        return;
+    }
+
+    match (&status.address, &status.bind_error) {
+        (Some(address), _) => {
+            ui.label(format!("{name}: listening on {address}"));
+        }
+        (None, Some(error)) => {
+            ui.label(RichText::new(format!("{name}: bind failed")).color(Color32::RED))
+                .on_hover_text(error);
+        }
+        (None, None) => {
+            ui.label(format!("{name}: starting"));
+        }
+    }
+
+    if let Some(error) = &status.last_handshake_error {
+        ui.label(
+            RichText::new(format!("({} failed connections)", status.handshake_errors))
+                .color(Color32::YELLOW),
+        )
+        .on_hover_text(error);
     }
 }